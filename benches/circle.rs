@@ -0,0 +1,76 @@
+use std::{
+    io::{Read, Write},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use ruisutil::{bytes::CircleBuf, Context};
+
+const TOTAL: usize = 64 * 1024 * 1024;
+const CHUNK: usize = 4096;
+const RING: usize = 64 * 1024;
+
+fn mutex_ring() -> Duration {
+    let ctx = Context::background(None);
+    let buf = Arc::new(Mutex::new(CircleBuf::new(&ctx, RING)));
+    let bufc = buf.clone();
+    let tms = Instant::now();
+    let wtr = std::thread::spawn(move || {
+        let data = vec![7u8; CHUNK];
+        let mut wn = 0;
+        while wn < TOTAL {
+            let n = bufc.lock().unwrap().write(&data).unwrap_or(0);
+            if n == 0 {
+                std::thread::yield_now();
+            }
+            wn += n;
+        }
+    });
+    let mut data = vec![0u8; CHUNK];
+    let mut rn = 0;
+    while rn < TOTAL {
+        let n = buf.lock().unwrap().read(&mut data).unwrap_or(0);
+        if n == 0 {
+            std::thread::yield_now();
+        }
+        rn += n;
+    }
+    wtr.join().unwrap();
+    tms.elapsed()
+}
+
+fn spsc_ring() -> Duration {
+    let ctx = Context::background(None);
+    let (mut prod, mut cons) = CircleBuf::new(&ctx, RING).split();
+    let tms = Instant::now();
+    let wtr = std::thread::spawn(move || {
+        let data = vec![7u8; CHUNK];
+        let mut wn = 0;
+        while wn < TOTAL {
+            prod.write_all(&data).unwrap();
+            wn += data.len();
+        }
+    });
+    let mut data = vec![0u8; CHUNK];
+    let mut rn = 0;
+    while rn < TOTAL {
+        rn += cons.read(&mut data).unwrap();
+    }
+    wtr.join().unwrap();
+    tms.elapsed()
+}
+
+fn report(name: &str, dur: Duration) {
+    let mbs = TOTAL as f64 / 1024f64 / 1024f64 / dur.as_secs_f64();
+    println!(
+        "{:<12} {:>10.3}ms {:>10.2}MB/s",
+        name,
+        dur.as_secs_f64() * 1000f64,
+        mbs
+    );
+}
+
+fn main() {
+    report("mutex", mutex_ring());
+    report("spsc", spsc_ring());
+}
@@ -50,7 +50,10 @@ where
             if e.kind() == std::io::ErrorKind::TimedOut {
                 let msg = format!("{}", e);
                 if msg == "future timed out" {
-                    Err(crate::errs::Error::new(msg, std::io::ErrorKind::TimedOut))
+                    Err(crate::errs::Error::with_kind(
+                        crate::errs::ErrorKind::Timeout,
+                        msg,
+                    ))
                 } else {
                     Ok(Err(e))
                 }
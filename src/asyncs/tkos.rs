@@ -55,9 +55,9 @@ where
 {
     match timeout(duration, future).await {
         Ok(v) => Ok(v),
-        Err(_e) => Err(crate::errs::Error::new(
+        Err(_e) => Err(crate::errs::Error::with_kind(
+            crate::errs::ErrorKind::Timeout,
             "future timed out",
-            std::io::ErrorKind::TimedOut,
         )),
     }
 }
@@ -234,6 +234,9 @@ impl ByteBoxBuf {
     pub fn len(&self) -> usize {
         self.count
     }
+    pub fn chunks(&self) -> usize {
+        self.list.len()
+    }
     pub fn frtlen(&self) -> usize {
         if let Some(v) = self.list.front() {
             v.len()
@@ -1,11 +1,6 @@
-use std::{
-    io::{self, Read, Write},
-    sync::Arc,
-    time::Duration,
-};
-
-#[cfg(feature="asyncs")]
-use async_std::task;
+#[cfg(any(feature = "asyncs", feature = "tokios"))]
+use std::task::Poll;
+use std::io::{self, Read, Write};
 
 pub struct CircleBuf {
     ctx: crate::Context,
@@ -14,6 +9,12 @@ pub struct CircleBuf {
     start: usize,
     end: usize,
     size: usize,
+    overwrite: bool,
+
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    wk_can_read: Option<std::task::Waker>,
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    wk_can_write: Option<std::task::Waker>,
 }
 
 impl CircleBuf {
@@ -25,11 +26,41 @@ impl CircleBuf {
             start: 0,
             end: 0,
             size: ln,
+            overwrite: false,
+
+            #[cfg(any(feature = "asyncs", feature = "tokios"))]
+            wk_can_read: None,
+            #[cfg(any(feature = "asyncs", feature = "tokios"))]
+            wk_can_write: None,
         }
     }
+    /// When enabled, `put_byte` and `write` drop the oldest bytes instead of failing on a full buffer.
+    pub fn set_overwrite(&mut self, on: bool) -> &mut Self {
+        self.overwrite = on;
+        self
+    }
+    pub fn is_overwrite(&self) -> bool {
+        self.overwrite
+    }
+
+    /// Splits the buffer into a lock-free single-producer/single-consumer pair,
+    /// keeping any data that is still readable.
+    pub fn split(self) -> (super::Producer, super::Consumer) {
+        let ln = self.len();
+        super::spsc::split(self.ctx, self.data, self.start, ln)
+    }
 
     pub fn close(&self) {
         self.ctx.stop();
+        #[cfg(any(feature = "asyncs", feature = "tokios"))]
+        {
+            if let Some(v) = &self.wk_can_read {
+                v.wake_by_ref();
+            }
+            if let Some(v) = &self.wk_can_write {
+                v.wake_by_ref();
+            }
+        }
     }
     pub fn closed(&self) -> bool {
         self.ctx.done()
@@ -49,6 +80,19 @@ impl CircleBuf {
     pub fn clear(&mut self) {
         self.start = 0;
         self.end = 0;
+        self.notify_can_write();
+    }
+    fn notify_can_read(&mut self) {
+        #[cfg(any(feature = "asyncs", feature = "tokios"))]
+        if let Some(v) = self.wk_can_read.take() {
+            v.wake();
+        }
+    }
+    fn notify_can_write(&mut self) {
+        #[cfg(any(feature = "asyncs", feature = "tokios"))]
+        if let Some(v) = self.wk_can_write.take() {
+            v.wake();
+        }
     }
 
     pub fn put_byte(&mut self, b: u8) -> io::Result<()> {
@@ -60,13 +104,20 @@ impl CircleBuf {
             pos = 0;
         }
         if pos == self.start {
-            return Err(crate::ioerr(
-                "not has available buf",
-                Some(io::ErrorKind::OutOfMemory),
-            ));
+            if !self.overwrite {
+                return Err(crate::ioerr(
+                    "not has available buf",
+                    Some(io::ErrorKind::OutOfMemory),
+                ));
+            }
+            self.start += 1;
+            if self.start == self.size {
+                self.start = 0;
+            }
         }
         self.data[self.end] = b;
         self.end = pos;
+        self.notify_can_read();
         Ok(())
     }
     pub fn pop_byte(&mut self) -> io::Result<u8> {
@@ -85,49 +136,87 @@ impl CircleBuf {
             pos = 0;
         }
         self.start = pos;
+        self.notify_can_write();
         Ok(rt)
     }
-    #[cfg(feature="asyncs")]
-    pub async fn ayc_sleep(&self) -> io::Result<()> {
-        if self.closed() {
-            Err(crate::ioerr("ctx is end", None))
-        } else {
-            task::sleep(Duration::from_millis(1)).await;
-            Ok(())
+    /// Ready once the byte is stored, otherwise registers `cx` to be woken by the next read.
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    pub fn poll_put_byte(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+        b: u8,
+    ) -> Poll<io::Result<()>> {
+        match self.put_byte(b) {
+            Err(e) if e.kind() == io::ErrorKind::OutOfMemory => {
+                self.wk_can_write = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            rts => Poll::Ready(rts),
+        }
+    }
+    /// Ready once a byte is available, otherwise registers `cx` to be woken by the next write.
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    pub fn poll_pop_byte(&mut self, cx: &mut std::task::Context<'_>) -> Poll<io::Result<u8>> {
+        match self.pop_byte() {
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                self.wk_can_read = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            rts => Poll::Ready(rts),
         }
     }
-    #[cfg(feature="asyncs")]
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
     pub async fn ayc_put_byte(&mut self, b: u8) -> io::Result<()> {
+        crate::asyncs::poll_fn(|cx| self.poll_put_byte(cx, b)).await
+    }
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    pub async fn ayc_pop_byte(&mut self) -> io::Result<u8> {
+        crate::asyncs::poll_fn(|cx| self.poll_pop_byte(cx)).await
+    }
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    fn poll_read_buf(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
         if self.closed() {
-            return Err(crate::ioerr("ctx is end", None));
+            return Poll::Ready(Err(crate::ioerr("ctx is end", None)));
         }
-        loop {
-            match self.put_byte(b) {
-                Err(e) => {
-                    if e.kind() != io::ErrorKind::OutOfMemory {
-                        return Err(e);
-                    }
-                }
-                Ok(_) => return Ok(()),
-            }
-            self.ayc_sleep().await?;
+        if self.len() == 0 {
+            self.wk_can_read = Some(cx.waker().clone());
+            return Poll::Pending;
         }
+        Poll::Ready(self.read(buf))
     }
-    #[cfg(feature="asyncs")]
-    pub async fn ayc_pop_byte(&mut self) -> io::Result<u8> {
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    fn poll_write_buf(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
         if self.closed() {
-            return Err(crate::ioerr("ctx is end", None));
+            return Poll::Ready(Err(crate::ioerr("ctx is end", None)));
         }
-        loop {
-            match self.pop_byte() {
-                Err(e) => {
-                    if e.kind() != io::ErrorKind::InvalidData {
-                        return Err(e);
-                    }
-                }
-                Ok(v) => return Ok(v),
-            }
-            self.ayc_sleep().await?;
+        if self.overwrite || buf.is_empty() {
+            return Poll::Ready(self.write(buf));
+        }
+        // a partial write of what fits, `write` wants the whole buf in one region
+        let ln = buf.len().min(self.write_chunk());
+        if ln == 0 {
+            self.wk_can_write = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        Poll::Ready(self.write(&buf[..ln]))
+    }
+    /// Bytes `borrow_write_buf` can hand out in one go, one slot always stays free.
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    fn write_chunk(&self) -> usize {
+        if self.end < self.start {
+            self.start - self.end - 1
+        } else if self.start == 0 {
+            self.size.saturating_sub(self.end + 1)
+        } else {
+            self.size - self.end
         }
     }
     pub fn get_byte(&self, i: usize) -> io::Result<u8> {
@@ -180,8 +269,68 @@ impl CircleBuf {
             pos = 0;
         }
         self.start = pos;
+        self.notify_can_write();
         Ok(())
     }
+    /// Readable region as two slices, the second one is non-empty when the data wraps around.
+    pub fn contiguous_read_slices(&self) -> (&[u8], &[u8]) {
+        if self.start <= self.end {
+            (&self.data[self.start..self.end], &[])
+        } else {
+            (&self.data[self.start..self.size], &self.data[..self.end])
+        }
+    }
+    /// Copies up to `n` bytes from the front without consuming them.
+    pub fn peek(&self, n: usize) -> io::Result<Vec<u8>> {
+        if self.closed() {
+            return Err(crate::ioerr("ctx is end", None));
+        }
+        let (frt, bck) = self.contiguous_read_slices();
+        let ln1 = n.min(frt.len());
+        let ln2 = (n - ln1).min(bck.len());
+        let mut rts = Vec::with_capacity(ln1 + ln2);
+        rts.extend_from_slice(&frt[..ln1]);
+        rts.extend_from_slice(&bck[..ln2]);
+        Ok(rts)
+    }
+    /// Discards up to `n` bytes from the front, returns how many were dropped.
+    pub fn skip(&mut self, n: usize) -> io::Result<usize> {
+        let mut rn = 0;
+        while rn < n && self.len() > 0 {
+            let ln = self.borrow_read_buf(n - rn)?.len();
+            self.borrow_read_ok(ln)?;
+            rn += ln;
+        }
+        Ok(rn)
+    }
+    fn write_overwrite(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.closed() {
+            return Err(crate::ioerr("ctx is end", None));
+        }
+        // one slot always stays free to tell full from empty
+        let cap = self.size.saturating_sub(1);
+        if cap == 0 {
+            return Ok(0);
+        }
+        let bts = if buf.len() > cap {
+            &buf[buf.len() - cap..]
+        } else {
+            buf
+        };
+        let over = (self.len() + bts.len()).saturating_sub(cap);
+        if over > 0 {
+            self.skip(over)?;
+        }
+        let mut wn = 0;
+        while wn < bts.len() {
+            let bufs = self.borrow_write_buf(bts.len() - wn)?;
+            let ln = bufs.len();
+            bufs.copy_from_slice(&bts[wn..wn + ln]);
+            self.borrow_write_ok(ln)?;
+            wn += ln;
+        }
+        Ok(buf.len())
+    }
     pub fn borrow_write_buf(&mut self, ln: usize) -> io::Result<&mut [u8]> {
         if self.closed() {
             return Err(crate::ioerr("ctx is end", None));
@@ -201,15 +350,13 @@ impl CircleBuf {
                     Some(io::ErrorKind::InvalidData),
                 ));
             }
-        } else {
-            if pos >= self.size {
-                pos = self.size;
-                if self.start == 0 {
-                    return Err(crate::ioerr(
-                        "not has available buf3",
-                        Some(io::ErrorKind::InvalidData),
-                    ));
-                }
+        } else if pos >= self.size {
+            pos = self.size;
+            if self.start == 0 {
+                return Err(crate::ioerr(
+                    "not has available buf3",
+                    Some(io::ErrorKind::InvalidData),
+                ));
             }
         }
         Ok(&mut self.data[self.end..pos])
@@ -232,66 +379,86 @@ impl CircleBuf {
             pos = 0;
         }
         self.end = pos;
+        self.notify_can_read();
         Ok(())
     }
 }
 
-#[cfg(feature="asyncs")]
-impl async_std::io::Read for CircleBuf {
+#[cfg(feature = "asyncs")]
+impl crate::asyncs::AsyncRead for CircleBuf {
     fn poll_read(
         mut self: std::pin::Pin<&mut Self>,
-        cx: &mut task::Context<'_>,
+        cx: &mut std::task::Context<'_>,
         buf: &mut [u8],
-    ) -> task::Poll<io::Result<usize>> {
-        if self.closed() {
-            return task::Poll::Ready(Err(crate::ioerr("ctx is end", None)));
-        }
-        if self.len() <= 0 {
-            return task::Poll::Pending;
-        }
-        let bufs = self.borrow_read_buf(buf.len())?;
-        let ln = bufs.len();
-        buf[..ln].copy_from_slice(bufs);
-        if let Err(e) = self.borrow_read_ok(ln) {
-            return task::Poll::Ready(Err(e));
-        }
-        task::Poll::Ready(Ok(ln))
+    ) -> Poll<io::Result<usize>> {
+        self.poll_read_buf(cx, buf)
     }
 }
-#[cfg(feature="asyncs")]
-impl async_std::io::Write for CircleBuf {
+#[cfg(feature = "asyncs")]
+impl crate::asyncs::AsyncWrite for CircleBuf {
     fn poll_write(
         mut self: std::pin::Pin<&mut Self>,
-        cx: &mut task::Context<'_>,
+        cx: &mut std::task::Context<'_>,
         buf: &[u8],
-    ) -> task::Poll<io::Result<usize>> {
-        if self.closed() {
-            return task::Poll::Ready(Err(crate::ioerr("ctx is end", None)));
-        }
-        if self.avail() <= 0 {
-            return task::Poll::Pending;
-        }
-        let bufs = self.borrow_write_buf(buf.len())?;
-        let ln = bufs.len();
-        bufs.copy_from_slice(&buf[..bufs.len()]);
-        std::mem::drop(bufs);
-        self.borrow_write_ok(ln)?;
-        task::Poll::Ready(Ok(ln))
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_buf(cx, buf)
     }
 
     fn poll_flush(
         self: std::pin::Pin<&mut Self>,
-        cx: &mut task::Context<'_>,
-    ) -> task::Poll<io::Result<()>> {
-        task::Poll::Ready(Ok(()))
+        _cx: &mut std::task::Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
     }
 
     fn poll_close(
         self: std::pin::Pin<&mut Self>,
-        cx: &mut task::Context<'_>,
-    ) -> task::Poll<io::Result<()>> {
+        _cx: &mut std::task::Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.close();
+        Poll::Ready(Ok(()))
+    }
+}
+#[cfg(feature = "tokios")]
+impl crate::asyncs::AsyncRead for CircleBuf {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.poll_read_buf(cx, buf.initialize_unfilled()) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Ready(Ok(n)) => {
+                buf.advance(n);
+                Poll::Ready(Ok(()))
+            }
+        }
+    }
+}
+#[cfg(feature = "tokios")]
+impl crate::asyncs::AsyncWrite for CircleBuf {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.poll_write_buf(cx, buf)
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
         self.close();
-        task::Poll::Ready(Ok(()))
+        Poll::Ready(Ok(()))
     }
 }
 
@@ -327,10 +494,13 @@ impl Read for CircleBuf {
 }
 impl Write for CircleBuf {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.overwrite {
+            return self.write_overwrite(buf);
+        }
         let bufs = self.borrow_write_buf(buf.len())?;
         let ln = bufs.len();
         bufs.copy_from_slice(&buf[..bufs.len()]);
-        let _=bufs;
+        let _ = bufs;
         self.borrow_write_ok(ln)?;
         Ok(ln)
     }
@@ -0,0 +1,148 @@
+use std::{io, sync::Arc, time::Duration};
+
+use crate::asyncs::FutureExt;
+
+use super::ByteSteamBuf;
+
+/// One end of an in-memory pipe made by `ByteSteamBuf::duplex`.
+///
+/// Bytes written here are read by the peer and the other way round, `flush` does not
+/// wait for the peer to read so both ends may write before reading.
+/// `shutdownw` or dropping an end gives the peer EOF once it drained what was sent.
+pub struct ByteSteamDuplex {
+    rbuf: Arc<ByteSteamBuf>,
+    wbuf: Arc<ByteSteamBuf>,
+}
+
+impl ByteSteamBuf {
+    pub fn duplex(ctx: &crate::asyncs::Context, max: usize) -> (ByteSteamDuplex, ByteSteamDuplex) {
+        let ab = Arc::new(ByteSteamBuf::new(ctx, max, Duration::from_millis(100)));
+        let ba = Arc::new(ByteSteamBuf::new(ctx, max, Duration::from_millis(100)));
+        (
+            ByteSteamDuplex {
+                rbuf: ba.clone(),
+                wbuf: ab.clone(),
+            },
+            ByteSteamDuplex { rbuf: ab, wbuf: ba },
+        )
+    }
+}
+
+impl ByteSteamDuplex {
+    /// Signals EOF to the peer, the read side stays open.
+    pub fn shutdown_write(&self) {
+        self.wbuf.close();
+    }
+    pub fn close(&self) {
+        self.rbuf.close();
+        self.wbuf.close();
+    }
+
+    fn poll_reads(
+        &self,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return std::task::Poll::Ready(Ok(0));
+        }
+        match self.rbuf.poll_read_buf(cx, buf) {
+            // the peer closed its write side and everything sent has been read
+            std::task::Poll::Ready(Err(e)) if e.kind() == io::ErrorKind::BrokenPipe => {
+                std::task::Poll::Ready(Ok(0))
+            }
+            rst => rst,
+        }
+    }
+}
+
+impl Drop for ByteSteamDuplex {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(feature = "asyncs")]
+impl crate::asyncs::AsyncRead for ByteSteamDuplex {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        self.poll_reads(cx, buf)
+    }
+}
+#[cfg(feature = "asyncs")]
+impl crate::asyncs::AsyncWrite for ByteSteamDuplex {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        self.wbuf.poll_write_buf(cx, buf)
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        std::task::Poll::Ready(self.wbuf.done_err())
+    }
+
+    fn poll_close(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        self.shutdown_write();
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+#[cfg(feature = "tokios")]
+impl crate::asyncs::AsyncRead for ByteSteamDuplex {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        match self.poll_reads(cx, buf.initialize_unfilled()) {
+            std::task::Poll::Pending => std::task::Poll::Pending,
+            std::task::Poll::Ready(Err(e)) => std::task::Poll::Ready(Err(e)),
+            std::task::Poll::Ready(Ok(n)) => {
+                buf.advance(n);
+                std::task::Poll::Ready(Ok(()))
+            }
+        }
+    }
+}
+#[cfg(feature = "tokios")]
+impl crate::asyncs::AsyncWrite for ByteSteamDuplex {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<Result<usize, io::Error>> {
+        self.wbuf.poll_write_buf(cx, buf)
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), io::Error>> {
+        std::task::Poll::Ready(self.wbuf.done_err())
+    }
+
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), io::Error>> {
+        self.shutdown_write();
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+impl crate::asyncs::IO for ByteSteamDuplex {
+    fn shutdownw<'a>(&'a mut self) -> crate::asyncs::BoxFuture<'a, std::io::Result<()>> {
+        self.shutdown_write();
+        async { Ok(()) }.boxed()
+    }
+}
@@ -3,11 +3,17 @@ pub use ::bytes::*;
 pub use bytes::ByteBoxBuf;
 pub use circle::CircleBuf;
 #[cfg(any(feature = "asyncs", feature = "tokios"))]
-pub use stream::ByteSteamBuf;
+pub use duplex::ByteSteamDuplex;
+pub use spsc::{Consumer, Producer};
+#[cfg(any(feature = "asyncs", feature = "tokios"))]
+pub use stream::{ByteSteamBuf, SteamMetrics};
 
 mod bytes;
 mod circle;
 #[cfg(any(feature = "asyncs", feature = "tokios"))]
+mod duplex;
+mod spsc;
+#[cfg(any(feature = "asyncs", feature = "tokios"))]
 mod stream;
 
 pub fn conv_human_readable(sz: u64) -> String {
@@ -22,6 +28,31 @@ pub fn conv_human_readable(sz: u64) -> String {
     return format!("{}B", sz);
 }
 
+/// Reverse of `conv_human_readable`: "10MB", "1.5GB", "4k", "512", units are 1024 based.
+pub fn parse_human_readable(s: &str) -> std::io::Result<u64> {
+    let s = s.trim();
+    let idx = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (num, unit) = s.split_at(idx);
+    let err = || {
+        crate::ioerr(
+            format!("size '{}' err", s),
+            Some(std::io::ErrorKind::InvalidInput),
+        )
+    };
+    let num: f64 = num.parse().map_err(|_| err())?;
+    let mul: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return Err(err()),
+    };
+    Ok((num * mul as f64).round() as u64)
+}
+
 pub fn bytes_with_len(mut bts: Vec<u8>, n: usize) -> Bytes {
     if n <= 0 {
         return bts.into();
@@ -50,7 +81,9 @@ impl BytesCut for Bytes {
 
 #[cfg(test)]
 mod tests {
-    use super::ByteBoxBuf;
+    use std::io::{Read, Write};
+
+    use super::{ByteBoxBuf, CircleBuf};
 
     #[test]
     fn it_works() {
@@ -64,4 +97,291 @@ mod tests {
         let bts = buf.to_bytes();
         println!("datas({}/{}):{:?}", bts.len(), buf.len(), &bts[..]);
     }
+
+    #[test]
+    fn circle_split() {
+        let ctx = crate::Context::background(None);
+        let mut buf = CircleBuf::new(&ctx, 16);
+        buf.write(b"abc").unwrap();
+        let (mut prod, mut cons) = buf.split();
+        assert_eq!(cons.len(), 3);
+        assert_eq!(prod.write_slice(&[1u8; 20]).unwrap(), 13);
+        assert_eq!(prod.write_slice(b"x").unwrap(), 0);
+        let mut out = [0u8; 5];
+        assert_eq!(cons.read_slice(&mut out).unwrap(), 5);
+        assert_eq!(&out, b"abc\x01\x01");
+
+        let total = 100_000usize;
+        let wtr = std::thread::spawn(move || {
+            let data: Vec<u8> = (0..total).map(|i| i as u8).collect();
+            prod.write_all(&data).unwrap();
+        });
+        let mut got = Vec::new();
+        let mut tmp = [0u8; 7];
+        loop {
+            let n = cons.read(&mut tmp).unwrap();
+            if n == 0 {
+                break;
+            }
+            got.extend_from_slice(&tmp[..n]);
+        }
+        wtr.join().unwrap();
+        assert_eq!(got.len(), 11 + total);
+        assert!(got[11..].iter().enumerate().all(|(i, v)| *v == i as u8));
+    }
+    #[test]
+    fn circle_overwrite() {
+        let ctx = crate::Context::background(None);
+        let mut buf = CircleBuf::new(&ctx, 8);
+        buf.set_overwrite(true);
+        assert_eq!(buf.write(b"0123456").unwrap(), 7);
+        assert_eq!(buf.write(b"789").unwrap(), 3);
+        assert_eq!(buf.peek(100).unwrap(), b"3456789");
+        let (frt, bck) = buf.contiguous_read_slices();
+        assert_eq!([frt, bck].concat(), b"3456789");
+        assert!(!bck.is_empty());
+        buf.put_byte(b'a').unwrap();
+        assert_eq!(buf.peek(3).unwrap(), b"456");
+        assert_eq!(buf.skip(5).unwrap(), 5);
+        assert_eq!(buf.peek(10).unwrap(), b"9a");
+        assert_eq!(buf.write(b"abcdefghijk").unwrap(), 11);
+        assert_eq!(buf.peek(10).unwrap(), b"efghijk");
+        assert_eq!(buf.skip(100).unwrap(), 7);
+        assert_eq!(buf.len(), 0);
+
+        buf.set_overwrite(false);
+        buf.write_all(b"0123456").unwrap();
+        assert!(buf.put_byte(b'x').is_err());
+    }
+    #[cfg(feature = "tokios")]
+    #[test]
+    fn circle_async_io() {
+        use crate::asyncs::{AsyncReadExt, AsyncWriteExt};
+        let ctx = crate::Context::background(None);
+        let mut buf = CircleBuf::new(&ctx, 16);
+        crate::asyncs::block_on(async move {
+            let tmout = std::time::Duration::from_millis(10);
+            AsyncWriteExt::write_all(&mut buf, b"0123456789abcde").await?;
+            // full: the next put waits for a reader
+            assert!(crate::asyncs::timeout(tmout, buf.ayc_put_byte(b'x'))
+                .await
+                .is_err());
+            let mut tmp = [0u8; 10];
+            assert_eq!(AsyncReadExt::read(&mut buf, &mut tmp).await?, 10);
+            assert_eq!(&tmp, b"0123456789");
+            AsyncWriteExt::write_all(&mut buf, b"fghij").await?;
+            buf.ayc_put_byte(b'k').await?;
+            let mut out = Vec::new();
+            while buf.len() > 0 {
+                out.push(buf.ayc_pop_byte().await?);
+            }
+            assert_eq!(&out, b"abcdefghijk");
+            // empty: the next pop waits for a writer
+            assert!(crate::asyncs::timeout(tmout, buf.ayc_pop_byte())
+                .await
+                .is_err());
+            buf.shutdown().await?;
+            assert!(buf.ayc_pop_byte().await.is_err());
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[cfg(feature = "tokios")]
+    #[test]
+    fn circle_async_write_over_size() {
+        use crate::asyncs::{AsyncReadExt, AsyncWriteExt};
+        let ctx = crate::Context::background(None);
+        let (mut rdr, mut wtr) = tokio::io::split(CircleBuf::new(&ctx, 16));
+        crate::asyncs::block_on(async move {
+            let data: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+            let datac = data.clone();
+            let wtrs = crate::asyncs::task::spawn(async move {
+                AsyncWriteExt::write_all(&mut wtr, &datac).await
+            });
+            let mut out = vec![0u8; data.len()];
+            crate::asyncs::timeout(
+                std::time::Duration::from_secs(5),
+                AsyncReadExt::read_exact(&mut rdr, &mut out),
+            )
+            .await??;
+            assert_eq!(out, data);
+            wtrs.await??;
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    #[test]
+    fn circle_split_async() {
+        let ctx = crate::Context::background(None);
+        let (mut prod, mut cons) = CircleBuf::new(&ctx, 64).split();
+        crate::asyncs::block_on(async move {
+            let wtr = crate::asyncs::task::spawn(async move {
+                for i in 0..1000u32 {
+                    prod.ayc_write(&i.to_le_bytes()).await.unwrap();
+                }
+            });
+            let mut n = 0u32;
+            let mut tmp = [0u8; 4];
+            let mut pos = 0;
+            loop {
+                let ln = cons.ayc_read(&mut tmp[pos..]).await?;
+                if ln == 0 {
+                    break;
+                }
+                pos += ln;
+                if pos == 4 {
+                    assert_eq!(u32::from_le_bytes(tmp), n);
+                    n += 1;
+                    pos = 0;
+                }
+            }
+            let _ = wtr.await;
+            assert_eq!(n, 1000);
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[cfg(feature = "tokios")]
+    #[test]
+    fn steam_duplex() {
+        use crate::asyncs::{AsyncReadExt, AsyncWriteExt, IO};
+        crate::asyncs::block_on(async move {
+            let ctx = crate::asyncs::Context::new();
+            let (mut cli, mut srv) = super::ByteSteamBuf::duplex(&ctx, 8);
+            let srvs = crate::asyncs::task::spawn(async move {
+                let mut req = Vec::new();
+                AsyncReadExt::read_to_end(&mut srv, &mut req).await?;
+                assert_eq!(req.len(), 1000);
+                AsyncWriteExt::write_all(&mut srv, b"pong").await?;
+                srv.shutdownw().await?;
+                Ok::<_, std::io::Error>(srv)
+            });
+            let req = vec![7u8; 1000];
+            crate::write_all_async(&ctx, &mut cli, &req).await?;
+            cli.shutdownw().await?;
+            assert!(AsyncWriteExt::write_all(&mut cli, b"x").await.is_err());
+            let mut rsp = Vec::new();
+            AsyncReadExt::read_to_end(&mut cli, &mut rsp).await?;
+            assert_eq!(&rsp, b"pong");
+            let srv = srvs.await.unwrap()?;
+
+            std::mem::drop(srv);
+            let mut tmp = [0u8; 4];
+            assert_eq!(AsyncReadExt::read(&mut cli, &mut tmp).await?, 0);
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[cfg(feature = "tokios")]
+    #[test]
+    fn steam_write_metrics() {
+        use crate::asyncs::AsyncWriteExt;
+        crate::asyncs::block_on(async move {
+            let ctx = crate::asyncs::Context::new();
+            let mut buf = super::ByteSteamBuf::new(&ctx, 10, std::time::Duration::from_millis(20));
+            AsyncWriteExt::write_all(&mut buf, &[1u8; 6]).await?;
+            // full: keeps waiting over several internal timeouts, then gives up
+            let tmout = std::time::Duration::from_millis(100);
+            assert!(
+                crate::asyncs::timeout(tmout, AsyncWriteExt::write_all(&mut buf, &[2u8; 6]))
+                    .await
+                    .is_err()
+            );
+            assert_eq!(buf.metrics().blocked_count, 1);
+            assert_eq!(buf.pull().await.unwrap().len(), 6);
+            AsyncWriteExt::write_all(&mut buf, &[2u8; 6]).await?;
+            assert_eq!(buf.metrics().blocked_count, 1);
+            assert!(buf.metrics().blocked >= tmout);
+            Ok(())
+        })
+        .unwrap();
+    }
+    #[cfg(feature = "tokios")]
+    #[test]
+    fn steam_limits() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+        crate::asyncs::block_on(async move {
+            let ctx = crate::asyncs::Context::new();
+            let buf = Arc::new(super::ByteSteamBuf::new(
+                &ctx,
+                10,
+                std::time::Duration::from_millis(50),
+            ));
+            let highs = Arc::new(AtomicUsize::new(0));
+            let lows = Arc::new(AtomicUsize::new(0));
+            buf.set_watermarks(8, 2);
+            let hc = highs.clone();
+            buf.on_high_watermark(move |_| {
+                hc.fetch_add(1, Ordering::SeqCst);
+            });
+            let lc = lows.clone();
+            buf.on_low_watermark(move |_| {
+                lc.fetch_add(1, Ordering::SeqCst);
+            });
+
+            buf.push(vec![1u8; 6]).await?;
+            let e = buf.try_push(vec![2u8; 6]).await.unwrap_err();
+            assert_eq!(e.kind(), std::io::ErrorKind::WouldBlock);
+            let bufc = buf.clone();
+            let wtr = crate::asyncs::task::spawn(async move { bufc.push(vec![2u8; 6]).await });
+            crate::asyncs::sleep(std::time::Duration::from_millis(100)).await;
+            assert_eq!(buf.len().await, 6);
+            assert_eq!(buf.pull().await.unwrap().len(), 6);
+            wtr.await.unwrap()?;
+            assert_eq!(buf.len().await, 6);
+            assert_eq!(buf.metrics().blocked_count, 1);
+            assert!(buf.metrics().blocked >= std::time::Duration::from_millis(50));
+            assert_eq!(highs.load(Ordering::SeqCst), 0);
+            buf.push(vec![3u8; 4]).await?;
+            assert_eq!(highs.load(Ordering::SeqCst), 1);
+            buf.pull().await;
+            buf.pull().await;
+            assert_eq!(lows.load(Ordering::SeqCst), 1);
+
+            // chunk limit
+            buf.set_max_chunks(2);
+            buf.push(vec![4u8; 1]).await?;
+            buf.push(vec![4u8; 1]).await?;
+            assert!(buf.try_push(vec![4u8; 1]).await.is_err());
+            buf.clear().await;
+            buf.set_max_chunks(0);
+
+            // one chunk bigger than the limit still fits into an empty buffer
+            buf.push(vec![5u8; 20]).await?;
+            buf.clear().await;
+
+            // pull_size lets writers exceed the max only while it waits
+            let bufc = buf.clone();
+            let wtr = crate::asyncs::task::spawn(async move {
+                for _ in 0..4 {
+                    bufc.push(vec![6u8; 5]).await?;
+                }
+                Ok::<_, std::io::Error>(())
+            });
+            let got = buf.pull_size(None, 20).await?;
+            wtr.await.unwrap()?;
+            assert_eq!(got.len(), 20);
+            assert_eq!(buf.get_max(), 10);
+
+            // a callback may replace itself
+            let wk = Arc::downgrade(&buf);
+            let hc = highs.clone();
+            buf.on_high_watermark(move |_| {
+                hc.fetch_add(1, Ordering::SeqCst);
+                if let Some(b) = wk.upgrade() {
+                    b.on_high_watermark(|_| {});
+                }
+            });
+            buf.clear().await;
+            let n = highs.load(Ordering::SeqCst);
+            buf.push(vec![7u8; 9]).await?;
+            assert_eq!(highs.load(Ordering::SeqCst), n + 1);
+            Ok(())
+        })
+        .unwrap();
+    }
 }
@@ -0,0 +1,393 @@
+use std::{
+    cell::UnsafeCell,
+    io,
+    sync::{
+        atomic::{fence, AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::Duration,
+};
+
+/// Yields before falling back to the condvar, most waits are short.
+const SPIN_MAX: usize = 64;
+/// Waits only time out to notice a cancelled parent `ctx`, wakeups come from the other side.
+const CTX_CHECK: Duration = Duration::from_millis(100);
+
+/// Shared ring of a split `CircleBuf`.
+///
+/// `head`/`tail` run over `0..2*size` so that a full ring and an empty ring
+/// can be told apart without wasting a slot.
+struct Inner {
+    ctx: crate::Context,
+    data: Cells,
+    size: usize,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+
+    can_read: Parker,
+    can_write: Parker,
+}
+
+/// Bytes of the ring, the only part of `Inner` that is not `Sync` by itself.
+struct Cells(Box<[UnsafeCell<u8>]>);
+// Producer only touches `tail` and the free region, consumer only `head` and the filled region.
+unsafe impl Sync for Cells {}
+
+/// Parks the single waiter of one side, blocking or async.
+///
+/// The waiter sets `waiting` before re-checking the ring and the other side
+/// loads it after publishing `head`/`tail`, both behind a SeqCst fence, so
+/// either the waiter sees the new position or the notifier sees the waiter.
+struct Parker {
+    waiting: AtomicBool,
+    lk: Mutex<Option<std::task::Waker>>,
+    cond: Condvar,
+}
+
+impl Parker {
+    fn new() -> Self {
+        Self {
+            waiting: AtomicBool::new(false),
+            lk: Mutex::new(None),
+            cond: Condvar::new(),
+        }
+    }
+    fn notify(&self) {
+        fence(Ordering::SeqCst);
+        if self.waiting.load(Ordering::Relaxed) {
+            self.wake();
+        }
+    }
+    fn wake(&self) {
+        let wk = {
+            let mut lkv = match self.lk.lock() {
+                Ok(v) => v,
+                Err(e) => e.into_inner(),
+            };
+            self.waiting.store(false, Ordering::Relaxed);
+            self.cond.notify_all();
+            lkv.take()
+        };
+        if let Some(v) = wk {
+            v.wake();
+        }
+    }
+    /// Blocks until woken, unless `ready` already holds after registering.
+    fn wait<F: Fn() -> bool>(&self, ready: F) {
+        let lkv = match self.lk.lock() {
+            Ok(v) => v,
+            Err(e) => e.into_inner(),
+        };
+        self.waiting.store(true, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        if !ready() {
+            let _ = self.cond.wait_timeout(lkv, CTX_CHECK);
+        }
+        self.waiting.store(false, Ordering::Relaxed);
+    }
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    fn poll_wait<F: Fn() -> bool>(
+        &self,
+        cx: &mut std::task::Context<'_>,
+        ready: F,
+    ) -> std::task::Poll<io::Result<()>> {
+        if ready() {
+            return std::task::Poll::Ready(Ok(()));
+        }
+        {
+            let mut lkv = match self.lk.lock() {
+                Ok(v) => v,
+                Err(e) => e.into_inner(),
+            };
+            *lkv = Some(cx.waker().clone());
+            self.waiting.store(true, Ordering::Relaxed);
+        }
+        fence(Ordering::SeqCst);
+        // a read/write between the first check and registering
+        if ready() {
+            return std::task::Poll::Ready(Ok(()));
+        }
+        std::task::Poll::Pending
+    }
+}
+
+/// Writing half of a split `CircleBuf`.
+pub struct Producer {
+    inner: Arc<Inner>,
+}
+/// Reading half of a split `CircleBuf`.
+pub struct Consumer {
+    inner: Arc<Inner>,
+}
+
+pub(super) fn split(
+    ctx: crate::Context,
+    data: Box<[u8]>,
+    start: usize,
+    ln: usize,
+) -> (Producer, Consumer) {
+    let size = data.len();
+    let data = Cells(unsafe { Box::from_raw(Box::into_raw(data) as *mut [UnsafeCell<u8>]) });
+    let inner = Arc::new(Inner {
+        can_read: Parker::new(),
+        can_write: Parker::new(),
+
+        ctx,
+        data,
+        size,
+        head: AtomicUsize::new(start),
+        tail: AtomicUsize::new(start + ln),
+    });
+    (
+        Producer {
+            inner: inner.clone(),
+        },
+        Consumer { inner },
+    )
+}
+
+impl Inner {
+    fn closed(&self) -> bool {
+        self.ctx.done()
+    }
+    fn close(&self) {
+        self.ctx.stop();
+        self.can_read.wake();
+        self.can_write.wake();
+    }
+    fn distance(&self, head: usize, tail: usize) -> usize {
+        if tail >= head {
+            tail - head
+        } else {
+            tail + 2 * self.size - head
+        }
+    }
+    fn advance(&self, pos: usize, n: usize) -> usize {
+        let v = pos + n;
+        if v >= 2 * self.size {
+            v - 2 * self.size
+        } else {
+            v
+        }
+    }
+    fn index(&self, pos: usize) -> usize {
+        if pos >= self.size {
+            pos - self.size
+        } else {
+            pos
+        }
+    }
+    fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        self.distance(head, tail)
+    }
+    fn ptr(&self) -> *mut u8 {
+        UnsafeCell::raw_get(self.data.0.as_ptr())
+    }
+    fn readable(&self) -> bool {
+        self.closed() || self.len() > 0
+    }
+    fn writable(&self) -> bool {
+        self.closed() || self.len() < self.size
+    }
+}
+
+impl Producer {
+    pub fn close(&self) {
+        self.inner.close();
+    }
+    pub fn closed(&self) -> bool {
+        self.inner.closed()
+    }
+    pub fn capacity(&self) -> usize {
+        self.inner.size
+    }
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.inner.len() == 0
+    }
+    pub fn avail(&self) -> usize {
+        self.inner.size - self.inner.len()
+    }
+
+    /// Copies as much of `buf` as fits, returns 0 when the ring is full.
+    pub fn write_slice(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.inner.closed() {
+            return Err(crate::ioerr("ctx is end", Some(io::ErrorKind::BrokenPipe)));
+        }
+        let head = self.inner.head.load(Ordering::Acquire);
+        let tail = self.inner.tail.load(Ordering::Relaxed);
+        let ln = self.inner.distance(head, tail);
+        let n = buf.len().min(self.inner.size - ln);
+        if n == 0 {
+            return Ok(0);
+        }
+        let idx = self.inner.index(tail);
+        let first = n.min(self.inner.size - idx);
+        unsafe {
+            let ptr = self.inner.ptr();
+            std::ptr::copy_nonoverlapping(buf.as_ptr(), ptr.add(idx), first);
+            std::ptr::copy_nonoverlapping(buf[first..].as_ptr(), ptr, n - first);
+        }
+        self.inner
+            .tail
+            .store(self.inner.advance(tail, n), Ordering::Release);
+        self.inner.can_read.notify();
+        Ok(n)
+    }
+    /// Blocks until all of `buf` is written or the ring is closed.
+    pub fn write_wait(&mut self, buf: &[u8]) -> io::Result<()> {
+        let mut pos = 0;
+        let mut spins = 0;
+        while pos < buf.len() {
+            let n = self.write_slice(&buf[pos..])?;
+            if n > 0 {
+                spins = 0;
+            } else if spins < SPIN_MAX {
+                spins += 1;
+                std::thread::yield_now();
+            } else {
+                let inner = &self.inner;
+                inner.can_write.wait(|| inner.writable());
+            }
+            pos += n;
+        }
+        Ok(())
+    }
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    pub async fn ayc_write(&mut self, buf: &[u8]) -> io::Result<()> {
+        let mut pos = 0;
+        while pos < buf.len() {
+            let n = self.write_slice(&buf[pos..])?;
+            if n == 0 {
+                let inner = &self.inner;
+                let _ = crate::asyncs::timeouts(
+                    CTX_CHECK,
+                    crate::asyncs::poll_fn(|cx| inner.can_write.poll_wait(cx, || inner.writable())),
+                )
+                .await;
+            }
+            pos += n;
+        }
+        Ok(())
+    }
+}
+
+impl Consumer {
+    pub fn close(&self) {
+        self.inner.close();
+    }
+    pub fn closed(&self) -> bool {
+        self.inner.closed()
+    }
+    pub fn capacity(&self) -> usize {
+        self.inner.size
+    }
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.inner.len() == 0
+    }
+
+    /// Copies up to `buf.len()` readable bytes, returns 0 when the ring is empty.
+    /// Data written before `close` can still be read out.
+    pub fn read_slice(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // check `closed` before loading `tail` so bytes written ahead of `close` are not missed
+        let closed = self.inner.closed();
+        let head = self.inner.head.load(Ordering::Relaxed);
+        let tail = self.inner.tail.load(Ordering::Acquire);
+        let ln = self.inner.distance(head, tail);
+        let n = buf.len().min(ln);
+        if n == 0 {
+            if closed {
+                return Err(crate::ioerr("ctx is end", Some(io::ErrorKind::BrokenPipe)));
+            }
+            return Ok(0);
+        }
+        let idx = self.inner.index(head);
+        let first = n.min(self.inner.size - idx);
+        unsafe {
+            let ptr = self.inner.ptr();
+            std::ptr::copy_nonoverlapping(ptr.add(idx), buf.as_mut_ptr(), first);
+            std::ptr::copy_nonoverlapping(ptr, buf[first..].as_mut_ptr(), n - first);
+        }
+        self.inner
+            .head
+            .store(self.inner.advance(head, n), Ordering::Release);
+        self.inner.can_write.notify();
+        Ok(n)
+    }
+    /// Blocks until some data is readable, returns 0 once closed and drained.
+    pub fn read_wait(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut spins = 0;
+        loop {
+            match self.read_slice(buf) {
+                Ok(0) => {}
+                Ok(n) => return Ok(n),
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(0),
+                Err(e) => return Err(e),
+            }
+            if spins < SPIN_MAX {
+                spins += 1;
+                std::thread::yield_now();
+            } else {
+                let inner = &self.inner;
+                inner.can_read.wait(|| inner.readable());
+            }
+        }
+    }
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    pub async fn ayc_read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.read_slice(buf) {
+                Ok(0) => {}
+                Ok(n) => return Ok(n),
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(0),
+                Err(e) => return Err(e),
+            }
+            let inner = &self.inner;
+            let _ = crate::asyncs::timeouts(
+                CTX_CHECK,
+                crate::asyncs::poll_fn(|cx| inner.can_read.poll_wait(cx, || inner.readable())),
+            )
+            .await;
+        }
+    }
+}
+
+impl Drop for Producer {
+    fn drop(&mut self) {
+        self.inner.close();
+    }
+}
+impl Drop for Consumer {
+    fn drop(&mut self) {
+        self.inner.close();
+    }
+}
+
+impl io::Write for Producer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_wait(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+impl io::Read for Consumer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_wait(buf)
+    }
+}
@@ -1,8 +1,11 @@
 use std::{
     future::Future,
     io,
-    sync::atomic::{AtomicUsize, Ordering},
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 use asyncs::sync::RwLock;
@@ -11,16 +14,35 @@ use crate::{asyncs, bytes::BytesCut, sync::WakerFut};
 
 use super::ByteBoxBuf;
 
+type WatermarkFn = Arc<dyn Fn(usize) + Send + Sync>;
+
 pub struct ByteSteamBuf {
     ctx: crate::asyncs::Context,
     buf: RwLock<ByteBoxBuf>,
     max: AtomicUsize,
+    max_chunks: AtomicUsize,
+    // temporary byte limit raised by `pull_size` while it waits,
+    // the largest of `needs`, one entry per waiting reader
+    need: AtomicUsize,
+    needs: std::sync::Mutex<Vec<usize>>,
     tmout: Duration,
+
+    high: AtomicUsize,
+    low: AtomicUsize,
+    above_high: AtomicBool,
+    on_high: std::sync::Mutex<Option<WatermarkFn>>,
+    on_low: std::sync::Mutex<Option<WatermarkFn>>,
+
+    blocked_ns: AtomicU64,
+    blocked_cnt: AtomicU64,
+    // `poll_write_buf` makes a new push future per poll, its wait lives here instead
+    poll_blocked: std::sync::Mutex<Option<Instant>>,
+
     wkr_can_read: WakerFut,
     wkr_can_write: WakerFut,
 
-    wk_can_read: Option<std::task::Waker>,
-    wk_can_write: Option<std::task::Waker>,
+    wk_can_read: futures::task::AtomicWaker,
+    wk_can_write: futures::task::AtomicWaker,
 }
 
 impl ByteSteamBuf {
@@ -30,12 +52,26 @@ impl ByteSteamBuf {
             ctx: ctx.clone(),
             buf: RwLock::new(ByteBoxBuf::new()),
             max: AtomicUsize::new(max),
-            tmout: tmout,
+            max_chunks: AtomicUsize::new(0),
+            need: AtomicUsize::new(0),
+            needs: std::sync::Mutex::new(Vec::new()),
+            tmout,
+
+            high: AtomicUsize::new(0),
+            low: AtomicUsize::new(0),
+            above_high: AtomicBool::new(false),
+            on_high: std::sync::Mutex::new(None),
+            on_low: std::sync::Mutex::new(None),
+
+            blocked_ns: AtomicU64::new(0),
+            blocked_cnt: AtomicU64::new(0),
+            poll_blocked: std::sync::Mutex::new(None),
+
             wkr_can_read: WakerFut::new(&ctx),
             wkr_can_write: WakerFut::new(&ctx),
 
-            wk_can_read: None,
-            wk_can_write: None,
+            wk_can_read: futures::task::AtomicWaker::new(),
+            wk_can_write: futures::task::AtomicWaker::new(),
         }
     }
     pub fn ctx(&self) -> &crate::asyncs::Context {
@@ -76,7 +112,9 @@ impl ByteSteamBuf {
     pub async fn clear(&self) {
         let mut lkv = self.buf.write().await;
         lkv.clear();
+        std::mem::drop(lkv);
         self.notify_all();
+        self.check_watermark(0);
     }
     pub async fn push_all(&self, data: &ByteBoxBuf) -> io::Result<usize> {
         let mut ln = 0;
@@ -87,44 +125,171 @@ impl ByteSteamBuf {
     }
 
     pub async fn push_front<T: Into<bytes::Bytes>>(&self, data: T) -> io::Result<usize> {
-        if self.get_max() > 0 {
-            loop {
-                self.done_err()?;
-                if self.buf.read().await.len() <= self.get_max() {
-                    break;
-                }
-                let _ = asyncs::timeouts(self.tmout.clone(), self.wkr_can_write.clone()).await;
-            }
-        }
+        self.pushs(data.into(), true, false).await
+    }
+    pub async fn push<T: Into<bytes::Bytes>>(&self, data: T) -> io::Result<usize> {
+        self.pushs(data.into(), false, false).await
+    }
+    /// Like `push` but fails with `WouldBlock` instead of waiting for room.
+    pub async fn try_push<T: Into<bytes::Bytes>>(&self, data: T) -> io::Result<usize> {
         self.done_err()?;
-        let mut lkv = self.buf.write().await;
         let dt = data.into();
         let ln = dt.len();
-        lkv.push_front(dt);
-        self.notify_all_can_read();
+        let mut lkv = self.buf.write().await;
+        if !self.can_push(&lkv, ln) {
+            return Err(crate::ioerr(
+                "buff is full",
+                Some(io::ErrorKind::WouldBlock),
+            ));
+        }
+        lkv.push(dt);
+        let lns = lkv.len();
+        std::mem::drop(lkv);
+        self.pushed(lns);
         Ok(ln)
     }
-    pub async fn push<T: Into<bytes::Bytes>>(&self, data: T) -> io::Result<usize> {
-        if self.get_max() > 0 {
-            loop {
-                self.done_err()?;
-                if self.buf.read().await.len() <= self.get_max() {
-                    break;
+    /// Room is reserved under the write lock, so concurrent writers never overshoot the limits.
+    /// A chunk larger than the byte limit is accepted once the buffer is empty.
+    /// `polled` keeps the blocked metrics in `poll_blocked`, the future is dropped on every Pending.
+    async fn pushs(&self, dt: bytes::Bytes, front: bool, polled: bool) -> io::Result<usize> {
+        let ln = dt.len();
+        let mut blocked = false;
+        loop {
+            if let Err(e) = self.done_err() {
+                if polled {
+                    self.poll_wait_end();
+                }
+                return Err(e);
+            }
+            let mut lkv = self.buf.write().await;
+            if self.can_push(&lkv, ln) {
+                if front {
+                    lkv.push_front(dt);
+                } else {
+                    lkv.push(dt);
                 }
+                let lns = lkv.len();
+                std::mem::drop(lkv);
+                if polled {
+                    self.poll_wait_end();
+                }
+                self.pushed(lns);
+                return Ok(ln);
+            }
+            std::mem::drop(lkv);
+            if polled {
+                self.poll_wait_begin();
                 let _ = self
                     .ctx
-                    .child_timeout(self.tmout.clone())
+                    .child_timeout(self.tmout)
                     .wait_futs(self.wkr_can_write.clone())
                     .await;
+                continue;
+            }
+            if !blocked {
+                blocked = true;
+                self.blocked_cnt.fetch_add(1, Ordering::SeqCst);
             }
+            // counted however the wait ends, the future may also be dropped
+            let start = Instant::now();
+            let _dfs = crate::defers(|| {
+                self.blocked_ns
+                    .fetch_add(start.elapsed().as_nanos() as u64, Ordering::SeqCst);
+            });
+            let _ = self
+                .ctx
+                .child_timeout(self.tmout)
+                .wait_futs(self.wkr_can_write.clone())
+                .await;
         }
-        self.done_err()?;
-        let mut lkv = self.buf.write().await;
-        let dt = data.into();
-        let ln = dt.len();
-        lkv.push(dt);
+    }
+    fn poll_wait_begin(&self) {
+        let mut lkv = match self.poll_blocked.lock() {
+            Ok(v) => v,
+            Err(e) => e.into_inner(),
+        };
+        if lkv.is_none() {
+            *lkv = Some(Instant::now());
+            self.blocked_cnt.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+    fn poll_wait_end(&self) {
+        let start = match self.poll_blocked.lock() {
+            Ok(mut v) => v.take(),
+            Err(e) => e.into_inner().take(),
+        };
+        if let Some(v) = start {
+            self.blocked_ns
+                .fetch_add(v.elapsed().as_nanos() as u64, Ordering::SeqCst);
+        }
+    }
+    fn can_push(&self, lkv: &ByteBoxBuf, ln: usize) -> bool {
+        let max = self.get_max().max(self.need.load(Ordering::SeqCst));
+        if max > 0 && lkv.len() > 0 && lkv.len() + ln > max {
+            return false;
+        }
+        let maxc = self.get_max_chunks();
+        if maxc > 0 && ln > 0 && lkv.chunks() >= maxc {
+            return false;
+        }
+        true
+    }
+    fn pushed(&self, lns: usize) {
         self.notify_all_can_read();
-        Ok(ln)
+        self.check_watermark(lns);
+    }
+    fn check_watermark(&self, lns: usize) {
+        let high = self.high.load(Ordering::SeqCst);
+        if high == 0 {
+            return;
+        }
+        let cbs = if lns >= high {
+            if self.above_high.swap(true, Ordering::SeqCst) {
+                return;
+            }
+            &self.on_high
+        } else if lns <= self.low.load(Ordering::SeqCst) {
+            if !self.above_high.swap(false, Ordering::SeqCst) {
+                return;
+            }
+            &self.on_low
+        } else {
+            return;
+        };
+        // called unlocked, so the callback may set callbacks itself
+        let f = match cbs.lock() {
+            Ok(v) => v.clone(),
+            Err(e) => e.into_inner().clone(),
+        };
+        if let Some(f) = f {
+            f(lns);
+        }
+    }
+    /// `high` of 0 disables the watermark callbacks.
+    pub fn set_watermarks(&self, high: usize, low: usize) {
+        self.high.store(high, Ordering::SeqCst);
+        self.low.store(low.min(high), Ordering::SeqCst);
+        self.above_high.store(false, Ordering::SeqCst);
+    }
+    /// Called once the buffered bytes reach the high watermark.
+    pub fn on_high_watermark<F: Fn(usize) + Send + Sync + 'static>(&self, f: F) {
+        match self.on_high.lock() {
+            Ok(mut v) => *v = Some(Arc::new(f)),
+            Err(e) => *e.into_inner() = Some(Arc::new(f)),
+        }
+    }
+    /// Called once the buffered bytes drop back to the low watermark.
+    pub fn on_low_watermark<F: Fn(usize) + Send + Sync + 'static>(&self, f: F) {
+        match self.on_low.lock() {
+            Ok(mut v) => *v = Some(Arc::new(f)),
+            Err(e) => *e.into_inner() = Some(Arc::new(f)),
+        }
+    }
+    pub fn metrics(&self) -> SteamMetrics {
+        SteamMetrics {
+            blocked: Duration::from_nanos(self.blocked_ns.load(Ordering::SeqCst)),
+            blocked_count: self.blocked_cnt.load(Ordering::SeqCst),
+        }
     }
     pub async fn pull(&self) -> Option<bytes::Bytes> {
         while !self.ctx.cancelled() {
@@ -139,7 +304,10 @@ impl ByteSteamBuf {
         }
         let mut lkv = self.buf.write().await;
         let rts = lkv.pull();
+        let lns = lkv.len();
+        std::mem::drop(lkv);
         self.notify_all_can_write();
+        self.check_watermark(lns);
         rts
     }
     pub async fn pull_max(&self, max: usize) -> Option<bytes::Bytes> {
@@ -164,7 +332,10 @@ impl ByteSteamBuf {
                 Some(bt)
             }
         };
+        let lns = lkv.len();
+        std::mem::drop(lkv);
         self.notify_all_can_write();
+        self.check_watermark(lns);
         rts
     }
     pub async fn pull_size(
@@ -172,7 +343,10 @@ impl ByteSteamBuf {
         ctx: Option<&crate::asyncs::Context>,
         sz: usize,
     ) -> io::Result<ByteBoxBuf> {
-        self.more_max(sz).await;
+        // let writers fill up to `sz` without growing the configured max for good
+        self.add_need(sz);
+        let _dfs = crate::defers(|| self.del_need(sz));
+        self.notify_all_can_write();
         while !self.ctx.cancelled() {
             if let Some(v) = ctx {
                 v.done_err()?;
@@ -189,20 +363,38 @@ impl ByteSteamBuf {
         }
         let mut lkv = self.buf.write().await;
         let rts = lkv.cut_front(sz);
+        let lns = lkv.len();
+        std::mem::drop(lkv);
         self.notify_all_can_write();
+        self.check_watermark(lns);
         rts
     }
+    fn add_need(&self, sz: usize) {
+        let mut lkv = match self.needs.lock() {
+            Ok(v) => v,
+            Err(e) => e.into_inner(),
+        };
+        lkv.push(sz);
+        self.need.fetch_max(sz, Ordering::SeqCst);
+    }
+    fn del_need(&self, sz: usize) {
+        let mut lkv = match self.needs.lock() {
+            Ok(v) => v,
+            Err(e) => e.into_inner(),
+        };
+        if let Some(i) = lkv.iter().position(|v| *v == sz) {
+            lkv.swap_remove(i);
+        }
+        let need = lkv.iter().copied().max().unwrap_or(0);
+        self.need.store(need, Ordering::SeqCst);
+    }
     fn notify_all_can_read(&self) {
         self.wkr_can_read.notify_all();
-        if let Some(v) = &self.wk_can_read {
-            v.wake_by_ref();
-        }
+        self.wk_can_read.wake();
     }
     fn notify_all_can_write(&self) {
         self.wkr_can_write.notify_all();
-        if let Some(v) = &self.wk_can_write {
-            v.wake_by_ref();
-        }
+        self.wk_can_write.wake();
     }
     pub fn notify_all(&self) {
         self.notify_all_can_read();
@@ -224,6 +416,18 @@ impl ByteSteamBuf {
     }
     pub fn set_max(&self, max: usize) {
         self.max.store(max, Ordering::SeqCst);
+        self.notify_all_can_write();
+    }
+    /// Limit on queued chunks, 0 means unlimited.
+    pub fn get_max_chunks(&self) -> usize {
+        self.max_chunks.load(Ordering::SeqCst)
+    }
+    pub fn set_max_chunks(&self, max: usize) {
+        self.max_chunks.store(max, Ordering::SeqCst);
+        self.notify_all_can_write();
+    }
+    pub async fn chunks(&self) -> usize {
+        self.buf.read().await.chunks()
     }
     pub fn set_maxs(&self, max: usize) {
         let maxs = self.get_max();
@@ -244,31 +448,24 @@ impl ByteSteamBuf {
         lkv.get_byte(idx)
     }
 
+    // the rest goes back under the same lock: `poll_read_buf` drops this future
+    // when it is pending, nothing may be awaited between the pop and the push back
     async fn readbts(&self, ln: usize) -> std::io::Result<bytes::Bytes> {
-        match self.pull().await {
+        match self.pull_max(ln).await {
             None => Err(crate::ioerr(
                 "buff is closed?",
                 Some(std::io::ErrorKind::BrokenPipe),
             )),
-            Some(mut it) => {
-                let bt = it.split_tos(ln);
-                if it.len() > 0 {
-                    self.buf.write().await.push_front(it);
-                }
-                Ok(bt)
-            }
+            Some(bt) => Ok(bt),
         }
     }
-}
 
-#[cfg(feature = "asyncs")]
-impl crate::asyncs::AsyncRead for ByteSteamBuf {
-    fn poll_read(
-        mut self: std::pin::Pin<&mut Self>,
+    pub(super) fn poll_read_buf(
+        &self,
         cx: &mut std::task::Context<'_>,
         buf: &mut [u8],
     ) -> std::task::Poll<io::Result<usize>> {
-        self.wk_can_read = Some(cx.waker().clone());
+        self.wk_can_read.register(cx.waker());
         let rst = match std::pin::pin!(self.readbts(buf.len())).poll(cx) {
             std::task::Poll::Pending => return std::task::Poll::Pending,
             std::task::Poll::Ready(Err(e)) => Err(e),
@@ -280,44 +477,77 @@ impl crate::asyncs::AsyncRead for ByteSteamBuf {
         };
         std::task::Poll::Ready(rst)
     }
-}
-#[cfg(feature = "asyncs")]
-impl crate::asyncs::AsyncWrite for ByteSteamBuf {
-    fn poll_write(
-        mut self: std::pin::Pin<&mut Self>,
+    pub(super) fn poll_write_buf(
+        &self,
         cx: &mut std::task::Context<'_>,
         buf: &[u8],
     ) -> std::task::Poll<io::Result<usize>> {
-        self.wk_can_write = Some(cx.waker().clone());
+        self.wk_can_write.register(cx.waker());
         let bts = bytes::Bytes::copy_from_slice(buf);
-        let rst = match std::pin::pin!(self.push(bts)).poll(cx) {
+        let rst = match std::pin::pin!(self.pushs(bts, false, true)).poll(cx) {
             std::task::Poll::Pending => return std::task::Poll::Pending,
             std::task::Poll::Ready(Err(e)) => Err(e),
             std::task::Poll::Ready(Ok(_)) => Ok(buf.len()),
         };
         std::task::Poll::Ready(rst)
     }
-
-    fn poll_flush(
-        mut self: std::pin::Pin<&mut Self>,
+    pub(super) fn poll_flush_buf(
+        &self,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<io::Result<()>> {
-        if self.ctx.done() {
+        if self.ctx.cancelled() {
             return std::task::Poll::Ready(Err(crate::ioerr(
                 "buff is closed?",
                 Some(std::io::ErrorKind::BrokenPipe),
             )));
         }
-        self.wk_can_write = Some(cx.waker().clone());
+        self.wk_can_write.register(cx.waker());
         match std::pin::pin!(self.waits(None)).poll(cx) {
             std::task::Poll::Pending => std::task::Poll::Pending,
             std::task::Poll::Ready(_) => std::task::Poll::Ready(Ok(())),
         }
     }
+}
 
-    fn poll_close(
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SteamMetrics {
+    /// Total time writers spent waiting for room, summed over finished waits.
+    /// A blocked `AsyncWrite` counts as finished once a write goes through or fails.
+    pub blocked: Duration,
+    /// How many pushes had to wait.
+    pub blocked_count: u64,
+}
+
+#[cfg(feature = "asyncs")]
+impl crate::asyncs::AsyncRead for ByteSteamBuf {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        self.poll_read_buf(cx, buf)
+    }
+}
+#[cfg(feature = "asyncs")]
+impl crate::asyncs::AsyncWrite for ByteSteamBuf {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        self.poll_write_buf(cx, buf)
+    }
+
+    fn poll_flush(
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        self.poll_flush_buf(cx)
+    }
+
+    fn poll_close(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<io::Result<()>> {
         self.close();
         std::task::Poll::Ready(Ok(()))
@@ -326,60 +556,41 @@ impl crate::asyncs::AsyncWrite for ByteSteamBuf {
 #[cfg(feature = "tokios")]
 impl crate::asyncs::AsyncRead for ByteSteamBuf {
     fn poll_read(
-        mut self: std::pin::Pin<&mut Self>,
+        self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
         buf: &mut tokio::io::ReadBuf<'_>,
     ) -> std::task::Poll<io::Result<()>> {
-        self.wk_can_read = Some(cx.waker().clone());
-        let rst = match std::pin::pin!(self.readbts(buf.remaining())).poll(cx) {
-            std::task::Poll::Pending => return std::task::Poll::Pending,
-            std::task::Poll::Ready(Err(e)) => Err(e),
-            std::task::Poll::Ready(Ok(it)) => {
-                buf.put_slice(&it);
-                Ok(())
+        match self.poll_read_buf(cx, buf.initialize_unfilled()) {
+            std::task::Poll::Pending => std::task::Poll::Pending,
+            std::task::Poll::Ready(Err(e)) => std::task::Poll::Ready(Err(e)),
+            std::task::Poll::Ready(Ok(n)) => {
+                buf.advance(n);
+                std::task::Poll::Ready(Ok(()))
             }
-        };
-        std::task::Poll::Ready(rst)
+        }
     }
 }
 
 #[cfg(feature = "tokios")]
 impl crate::asyncs::AsyncWrite for ByteSteamBuf {
     fn poll_write(
-        mut self: std::pin::Pin<&mut Self>,
+        self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
         buf: &[u8],
     ) -> std::task::Poll<Result<usize, io::Error>> {
-        self.wk_can_write = Some(cx.waker().clone());
-        let bts = bytes::Bytes::copy_from_slice(buf);
-        let rst = match std::pin::pin!(self.push(bts)).poll(cx) {
-            std::task::Poll::Pending => return std::task::Poll::Pending,
-            std::task::Poll::Ready(Err(e)) => Err(e),
-            std::task::Poll::Ready(Ok(_)) => Ok(buf.len()),
-        };
-        std::task::Poll::Ready(rst)
+        self.poll_write_buf(cx, buf)
     }
 
     fn poll_flush(
-        mut self: std::pin::Pin<&mut Self>,
+        self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Result<(), io::Error>> {
-        if self.ctx.cancelled() {
-            return std::task::Poll::Ready(Err(crate::ioerr(
-                "buff is closed?",
-                Some(std::io::ErrorKind::BrokenPipe),
-            )));
-        }
-        self.wk_can_write = Some(cx.waker().clone());
-        match std::pin::pin!(self.waits(None)).poll(cx) {
-            std::task::Poll::Pending => std::task::Poll::Pending,
-            std::task::Poll::Ready(_) => std::task::Poll::Ready(Ok(())),
-        }
+        self.poll_flush_buf(cx)
     }
 
     fn poll_shutdown(
         self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
+        _cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Result<(), io::Error>> {
         self.close();
         std::task::Poll::Ready(Ok(()))
@@ -0,0 +1,272 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use serde::de::{self, Error as _, IntoDeserializer, Visitor};
+
+/// Deserialization error, names the dotted key it is about.
+#[derive(Debug)]
+pub struct Error {
+    key: Option<String>,
+    msg: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.key {
+            Some(k) if !k.is_empty() => write!(f, "config key `{}`: {}", k, self.msg),
+            _ => write!(f, "config: {}", self.msg),
+        }
+    }
+}
+impl std::error::Error for Error {}
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self {
+            key: None,
+            msg: msg.to_string(),
+        }
+    }
+}
+impl Error {
+    fn at(mut self, key: &str) -> Self {
+        if self.key.is_none() {
+            self.key = Some(key.to_string());
+        }
+        self
+    }
+}
+
+/// Dotted keys as a tree, `a.b=1` and `a.c=2` become map `a` with `b` and `c`.
+enum Node {
+    Leaf(String),
+    Map(BTreeMap<String, Node>),
+}
+
+pub(super) fn from_map<T: de::DeserializeOwned>(mp: &HashMap<String, String>) -> Result<T, Error> {
+    let mut root = BTreeMap::new();
+    for (k, v) in mp {
+        let mut cur = &mut root;
+        let mut parts = k.split('.').peekable();
+        while let Some(p) = parts.next() {
+            if parts.peek().is_none() {
+                // a map already there wins over a plain value
+                cur.entry(p.to_string())
+                    .or_insert_with(|| Node::Leaf(v.clone()));
+                break;
+            }
+            let ent = cur
+                .entry(p.to_string())
+                .or_insert_with(|| Node::Map(BTreeMap::new()));
+            if let Node::Leaf(_) = ent {
+                *ent = Node::Map(BTreeMap::new());
+            }
+            cur = match ent {
+                Node::Map(m) => m,
+                Node::Leaf(_) => unreachable!(),
+            };
+        }
+    }
+    T::deserialize(NodeDe {
+        node: &Node::Map(root),
+        key: String::new(),
+    })
+}
+
+struct NodeDe<'a> {
+    node: &'a Node,
+    key: String,
+}
+
+impl<'a> NodeDe<'a> {
+    fn leaf(&self, what: &str) -> Result<&'a str, Error> {
+        match self.node {
+            Node::Leaf(v) => Ok(v.as_str()),
+            Node::Map(_) => {
+                Err(Error::custom(format!("expected {}, found a section", what)).at(&self.key))
+            }
+        }
+    }
+    fn parse<T: std::str::FromStr>(&self, what: &str) -> Result<T, Error>
+    where
+        T::Err: fmt::Display,
+    {
+        let v = self.leaf(what)?;
+        v.trim()
+            .parse::<T>()
+            .map_err(|e| Error::custom(format!("invalid {} `{}`: {}", what, v, e)).at(&self.key))
+    }
+    fn child(&self, k: &str) -> String {
+        if self.key.is_empty() {
+            k.to_string()
+        } else {
+            format!("{}.{}", self.key, k)
+        }
+    }
+}
+
+macro_rules! de_num {
+    ($($f:ident $v:ident $t:ty,)*) => {
+        $(fn $f<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.$v(self.parse::<$t>(stringify!($t))?)
+        })*
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for NodeDe<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.node {
+            Node::Leaf(v) => visitor.visit_str(v),
+            Node::Map(_) => self.deserialize_map(visitor),
+        }
+    }
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.leaf("bool")?;
+        match super::parse_bool(v) {
+            Some(b) => visitor.visit_bool(b),
+            None => Err(Error::custom(format!("invalid bool `{}`", v)).at(&self.key)),
+        }
+    }
+    de_num! {
+        deserialize_i8 visit_i8 i8,
+        deserialize_i16 visit_i16 i16,
+        deserialize_i32 visit_i32 i32,
+        deserialize_i64 visit_i64 i64,
+        deserialize_u8 visit_u8 u8,
+        deserialize_u16 visit_u16 u16,
+        deserialize_u32 visit_u32 u32,
+        deserialize_u64 visit_u64 u64,
+        deserialize_f32 visit_f32 f32,
+        deserialize_f64 visit_f64 f64,
+        deserialize_char visit_char char,
+    }
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_str(self.leaf("string")?)
+    }
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_bytes(self.leaf("bytes")?.as_bytes())
+    }
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+    /// Comma separated list, see `KVConfig::get_list`.
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let items = super::split_list(self.leaf("list")?);
+        let nodes: Vec<Node> = items.into_iter().map(Node::Leaf).collect();
+        let key = self.key.clone();
+        visitor
+            .visit_seq(de::value::SeqDeserializer::new(
+                nodes.iter().enumerate().map(|(i, node)| NodeDe {
+                    node,
+                    key: format!("{}[{}]", &key, i),
+                }),
+            ))
+            .map_err(|e: Error| e.at(&self.key))
+    }
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let mp = match self.node {
+            Node::Map(m) => m,
+            Node::Leaf(v) => {
+                return Err(
+                    Error::custom(format!("expected a section, found value `{}`", v)).at(&self.key),
+                )
+            }
+        };
+        let it = mp.iter().map(|(k, node)| {
+            (
+                k.as_str(),
+                NodeDe {
+                    node,
+                    key: self.child(k),
+                },
+            )
+        });
+        visitor
+            .visit_map(de::value::MapDeserializer::new(it))
+            .map_err(|e| e.at(&self.key))
+    }
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_map(visitor)
+    }
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let v = self.leaf("enum")?;
+        visitor
+            .visit_enum(v.into_deserializer())
+            .map_err(|e: Error| e.at(&self.key))
+    }
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+}
+
+impl<'de, 'a> IntoDeserializer<'de, Error> for NodeDe<'a> {
+    type Deserializer = Self;
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// For `#[serde(deserialize_with = "ruisutil::conf::de_duration")]` on "30s" style values.
+pub fn de_duration<'de, D: de::Deserializer<'de>>(d: D) -> Result<std::time::Duration, D::Error> {
+    let s: String = de::Deserialize::deserialize(d)?;
+    crate::parse_duration(&s).map_err(de::Error::custom)
+}
+/// For `#[serde(deserialize_with = "ruisutil::conf::de_size")]` on "10MB" style values.
+pub fn de_size<'de, D: de::Deserializer<'de>>(d: D) -> Result<u64, D::Error> {
+    let s: String = de::Deserialize::deserialize(d)?;
+    crate::bytes::parse_human_readable(&s).map_err(de::Error::custom)
+}
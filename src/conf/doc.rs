@@ -0,0 +1,278 @@
+use std::{
+    fmt,
+    io::{self, Write},
+    path::Path,
+};
+
+use super::{
+    lexer::{Lexer, TokenKind},
+    quote_value, read_file, KVConfig,
+};
+
+/// A `KVConfig` file kept line by line, for tools that edit config files in place.
+///
+/// Comments, blank lines, sections and key order survive a `set`/`remove`
+/// and `to_string` round trip. `include` lines are kept but not followed,
+/// `get` only sees the keys of this file.
+pub struct KVDocument {
+    lines: Vec<Line>,
+    crlf: bool,
+}
+
+enum Line {
+    /// Blank, comment or include line.
+    Text(String),
+    /// `[name]`, prefix is `name.` or empty for `[ ]`.
+    Section {
+        text: String,
+        prefix: String,
+    },
+    Entry(Entry),
+}
+
+struct Entry {
+    key: String,
+    val: String,
+    // original text, several lines for a `\` continuation
+    text: String,
+    // text before and after the value, for rewriting it in place
+    head: String,
+    tail: String,
+}
+
+impl Default for KVDocument {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KVDocument {
+    pub fn new() -> Self {
+        Self {
+            lines: Vec::new(),
+            crlf: false,
+        }
+    }
+    pub fn from_file<P: AsRef<Path>>(pth: P) -> io::Result<Self> {
+        let pth = pth.as_ref();
+        let bts = read_file(pth)?;
+        Self::parses(&bts, &pth.display().to_string())
+    }
+    /// Same syntax and errors as `KVConfig::parse`.
+    pub fn parse(bts: &[u8]) -> io::Result<Self> {
+        Self::parses(bts, "<bytes>")
+    }
+    fn parses(bts: &[u8], name: &str) -> io::Result<Self> {
+        let conts = std::str::from_utf8(bts).map_err(|e| {
+            crate::ioerr(
+                format!("{}: not utf-8: {}", name, e),
+                Some(io::ErrorKind::InvalidData),
+            )
+        })?;
+        let mut doc = Self::new();
+        doc.crlf = conts.contains("\r\n");
+        for tk in Lexer::new(conts) {
+            let lineno = tk.lineno;
+            let kind = tk
+                .kind
+                .map_err(|e| crate::ioerr(format!("{}:{}: {}", name, lineno, e), Some(e.kind())))?;
+            let text = tk.text;
+            doc.lines.push(match kind {
+                TokenKind::Blank | TokenKind::Include(_) => Line::Text(text),
+                TokenKind::Section(prefix) => Line::Section { text, prefix },
+                TokenKind::Entry {
+                    key,
+                    val,
+                    start,
+                    end,
+                } => Line::Entry(Entry {
+                    key,
+                    val,
+                    head: tk.logical[..start].to_string(),
+                    tail: tk.logical[end..].to_string(),
+                    text,
+                }),
+            });
+        }
+        Ok(doc)
+    }
+
+    /// Last value of `key` in the file, like `KVConfig::get`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries()
+            .filter(|v| v.key == key)
+            .last()
+            .map(|v| v.val.as_str())
+    }
+    /// Keys in file order, without repeats.
+    pub fn keys(&self) -> Vec<&str> {
+        let mut rt: Vec<&str> = Vec::new();
+        for ent in self.entries() {
+            if !rt.contains(&ent.key.as_str()) {
+                rt.push(&ent.key);
+            }
+        }
+        rt
+    }
+    fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.lines.iter().filter_map(|v| match v {
+            Line::Entry(e) => Some(e),
+            _ => None,
+        })
+    }
+
+    /// Replaces the value in place, keeping the line's comment.
+    /// A new key goes after the last key of its `[section]`, or to the top level.
+    pub fn set<K: Into<String>, V: Into<String>>(&mut self, key: K, val: V) -> &mut Self {
+        let key = key.into();
+        let val = val.into();
+        let last = self.lines.iter_mut().rev().find_map(|v| match v {
+            Line::Entry(e) if e.key == key => Some(e),
+            _ => None,
+        });
+        match last {
+            Some(ent) => {
+                if ent.val != val {
+                    ent.text = format!("{}{}{}", ent.head, quote_value(&val), ent.tail);
+                    ent.val = val;
+                }
+            }
+            None => self.insert(key, val),
+        }
+        self
+    }
+    fn insert(&mut self, key: String, val: String) {
+        // (prefix, index to insert at) of the longest section that prefixes key
+        let mut best: Option<(usize, usize)> = None;
+        let mut top: Option<usize> = None;
+        let mut first_sect: Option<usize> = None;
+        let mut cur: Option<usize> = None;
+        for (i, ln) in self.lines.iter().enumerate() {
+            match ln {
+                Line::Section { prefix, .. } => {
+                    first_sect.get_or_insert(i);
+                    cur = if !prefix.is_empty()
+                        && key.len() > prefix.len()
+                        && key.starts_with(prefix.as_str())
+                        && !matches!(best, Some((n, _)) if prefix.len() < n)
+                    {
+                        best = Some((prefix.len(), i + 1));
+                        Some(prefix.len())
+                    } else {
+                        None
+                    };
+                }
+                Line::Entry(_) => {
+                    if first_sect.is_none() {
+                        top = Some(i + 1);
+                    } else if let (Some(n), Some(b)) = (cur, best.as_mut()) {
+                        if b.0 == n {
+                            b.1 = i + 1;
+                        }
+                    }
+                }
+                Line::Text(_) => {}
+            }
+        }
+        let (idx, local) = match best {
+            Some((n, idx)) => (idx, &key[n..]),
+            None => {
+                let idx = match (top, first_sect) {
+                    (Some(i), _) => i,
+                    (None, None) => self.lines.len(),
+                    // above the comments that lead the first section
+                    (None, Some(mut i)) => {
+                        while i > 0 {
+                            match &self.lines[i - 1] {
+                                Line::Text(t) if !t.trim().is_empty() => i -= 1,
+                                _ => break,
+                            }
+                        }
+                        i
+                    }
+                };
+                (idx, key.as_str())
+            }
+        };
+        let head = format!("{} = ", local);
+        let text = format!("{}{}", head, quote_value(&val));
+        self.lines.insert(
+            idx,
+            Line::Entry(Entry {
+                key,
+                val,
+                text,
+                head,
+                tail: String::new(),
+            }),
+        );
+    }
+    /// Removes every line of `key`, returns the value `get` had.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let rt = self.get(key).map(|v| v.to_string());
+        self.lines
+            .retain(|v| !matches!(v, Line::Entry(e) if e.key == key));
+        rt
+    }
+
+    /// The keys of this file as a `KVConfig`.
+    pub fn to_config(&self) -> KVConfig {
+        let mut kvc = KVConfig::new();
+        for ent in self.entries() {
+            kvc.mp.insert(ent.key.clone(), ent.val.clone());
+        }
+        kvc
+    }
+
+    /// Writes a temp file next to `pth` and renames it over `pth`,
+    /// so readers see either the old or the new file.
+    pub fn save<P: AsRef<Path>>(&self, pth: P) -> io::Result<()> {
+        let pth = pth.as_ref();
+        let name = match pth.file_name() {
+            Some(v) => v.to_string_lossy(),
+            None => {
+                return Err(crate::ioerr(
+                    format!("kv save {} is not a file", pth.display()),
+                    Some(io::ErrorKind::InvalidInput),
+                ))
+            }
+        };
+        let tmp = pth.with_file_name(format!(".{}.tmp{}", name, std::process::id()));
+        let rst = (|| {
+            let mut fl = std::fs::File::create(&tmp)?;
+            fl.write_all(self.to_string().as_bytes())?;
+            if let Ok(meta) = std::fs::metadata(pth) {
+                fl.set_permissions(meta.permissions())?;
+            }
+            fl.sync_all()?;
+            std::fs::rename(&tmp, pth)
+        })();
+        if let Err(e) = rst {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(crate::ioerr(
+                format!("kv save {} err:{}", pth.display(), e),
+                Some(e.kind()),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for KVDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let eol = if self.crlf { "\r\n" } else { "\n" };
+        for ln in &self.lines {
+            let text = match ln {
+                Line::Text(t) => t,
+                Line::Section { text, .. } => text,
+                Line::Entry(e) => &e.text,
+            };
+            if self.crlf {
+                write!(f, "{}{}", text.replace('\n', eol), eol)?;
+            } else {
+                write!(f, "{}{}", text, eol)?;
+            }
+        }
+        Ok(())
+    }
+}
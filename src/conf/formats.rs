@@ -0,0 +1,115 @@
+//! TOML and JSON files flattened to the dotted keys of `KVConfig`.
+//!
+//! Tables/objects join their keys with `.`, arrays of plain values become
+//! a comma separated list like `get_list` reads, arrays holding tables are
+//! indexed: `servers.0.host`. JSON `null` leaves the key out. An item holding
+//! a `,` would read as two, such arrays are indexed too: `hosts.0`.
+
+use std::{collections::HashMap, io};
+
+#[cfg(feature = "tomls")]
+pub(super) fn from_toml(bts: &[u8], name: &str) -> io::Result<HashMap<String, String>> {
+    let conts = std::str::from_utf8(bts).map_err(|e| fmt_err(name, e))?;
+    let tbl: toml::Table = toml::from_str(conts).map_err(|e| fmt_err(name, e))?;
+    let mut mp = HashMap::new();
+    for (k, v) in tbl {
+        flat_toml(&mut mp, k, v);
+    }
+    Ok(mp)
+}
+#[cfg(feature = "tomls")]
+fn flat_toml(mp: &mut HashMap<String, String>, key: String, v: toml::Value) {
+    use toml::Value;
+    match v {
+        Value::Table(tbl) => {
+            for (k, v) in tbl {
+                flat_toml(mp, format!("{}.{}", key, k), v);
+            }
+        }
+        Value::Array(ls)
+            if ls
+                .iter()
+                .any(|v| v.is_table() || v.is_array() || has_comma(v.as_str())) =>
+        {
+            for (i, v) in ls.into_iter().enumerate() {
+                flat_toml(mp, format!("{}.{}", key, i), v);
+            }
+        }
+        Value::Array(ls) => {
+            let items: Vec<String> = ls.into_iter().map(toml_scalar).collect();
+            mp.insert(key, items.join(","));
+        }
+        v => {
+            mp.insert(key, toml_scalar(v));
+        }
+    }
+}
+#[cfg(feature = "tomls")]
+fn toml_scalar(v: toml::Value) -> String {
+    match v {
+        toml::Value::String(s) => s,
+        v => v.to_string(),
+    }
+}
+
+#[cfg(feature = "jsons")]
+pub(super) fn from_json(bts: &[u8], name: &str) -> io::Result<HashMap<String, String>> {
+    let val: serde_json::Value = serde_json::from_slice(bts).map_err(|e| fmt_err(name, e))?;
+    let obj = match val {
+        serde_json::Value::Object(v) => v,
+        _ => return Err(fmt_err(name, "top level is not an object")),
+    };
+    let mut mp = HashMap::new();
+    for (k, v) in obj {
+        flat_json(&mut mp, k, v);
+    }
+    Ok(mp)
+}
+#[cfg(feature = "jsons")]
+fn flat_json(mp: &mut HashMap<String, String>, key: String, v: serde_json::Value) {
+    use serde_json::Value;
+    match v {
+        Value::Null => {}
+        Value::Object(obj) => {
+            for (k, v) in obj {
+                flat_json(mp, format!("{}.{}", key, k), v);
+            }
+        }
+        Value::Array(ls)
+            if ls
+                .iter()
+                .any(|v| v.is_object() || v.is_array() || has_comma(v.as_str())) =>
+        {
+            for (i, v) in ls.into_iter().enumerate() {
+                flat_json(mp, format!("{}.{}", key, i), v);
+            }
+        }
+        Value::Array(ls) => {
+            let items: Vec<String> = ls
+                .into_iter()
+                .filter(|v| !v.is_null())
+                .map(json_scalar)
+                .collect();
+            mp.insert(key, items.join(","));
+        }
+        v => {
+            mp.insert(key, json_scalar(v));
+        }
+    }
+}
+#[cfg(feature = "jsons")]
+fn json_scalar(v: serde_json::Value) -> String {
+    match v {
+        serde_json::Value::String(s) => s,
+        v => v.to_string(),
+    }
+}
+
+// an item `get_list` would split
+fn has_comma(v: Option<&str>) -> bool {
+    matches!(v, Some(s) if s.contains(','))
+}
+
+fn fmt_err<E: std::fmt::Display>(name: &str, e: E) -> io::Error {
+    crate::ioerr(format!("{}: {}", name, e), Some(io::ErrorKind::InvalidData))
+}
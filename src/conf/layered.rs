@@ -0,0 +1,185 @@
+use std::{
+    collections::HashMap,
+    fmt, io,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+use super::KVConfig;
+
+/// Where a `LayeredConfig` value came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    /// Name of the env var.
+    Env(String),
+    /// A `--key=value` argument.
+    Arg,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(p) => write!(f, "file {}", p.display()),
+            Source::Env(k) => write!(f, "env {}", k),
+            Source::Arg => write!(f, "argument"),
+        }
+    }
+}
+
+/// Defaults, files, env vars and command line merged into one `KVConfig`.
+///
+/// Layers added later override earlier ones, defaults never override anything:
+///
+/// ```no_run
+/// let mut cfg = ruisutil::conf::LayeredConfig::new();
+/// cfg.set_default("db.host", "127.0.0.1")
+///     .file_opt("/etc/app.conf")?
+///     .file_opt("app.conf")?
+///     .env("APP")
+///     .args(std::env::args().skip(1))?;
+/// println!("db.host={:?} from {:?}", cfg.get("db.host"), cfg.source_of("db.host"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct LayeredConfig {
+    cfg: KVConfig,
+    srcs: HashMap<String, Source>,
+}
+
+impl Default for LayeredConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for LayeredConfig {
+    type Target = KVConfig;
+    fn deref(&self) -> &KVConfig {
+        &self.cfg
+    }
+}
+
+impl LayeredConfig {
+    pub fn new() -> Self {
+        Self {
+            cfg: KVConfig::new(),
+            srcs: HashMap::new(),
+        }
+    }
+    pub fn config(&self) -> &KVConfig {
+        &self.cfg
+    }
+    pub fn source_of(&self, key: &str) -> Option<&Source> {
+        self.srcs.get(key)
+    }
+
+    fn put(&mut self, key: String, val: String, src: Source) {
+        self.srcs.insert(key.clone(), src);
+        self.cfg.mp.insert(key, val);
+    }
+
+    /// Used only when no other layer has `key`.
+    pub fn set_default<K: Into<String>, V: Into<String>>(&mut self, key: K, val: V) -> &mut Self {
+        let key = key.into();
+        if !self.cfg.mp.contains_key(&key) {
+            self.put(key, val.into(), Source::Default);
+        }
+        self
+    }
+    /// Loads `pth` with `KVConfig::load`, the file must exist.
+    pub fn file<P: AsRef<Path>>(&mut self, pth: P) -> io::Result<&mut Self> {
+        self.load(pth.as_ref())?;
+        Ok(self)
+    }
+    /// Like `file`, but a missing file is skipped.
+    /// Errors from inside an existing one, like a missing `include`, are returned.
+    pub fn file_opt<P: AsRef<Path>>(&mut self, pth: P) -> io::Result<&mut Self> {
+        if !pth.as_ref().exists() {
+            return Ok(self);
+        }
+        self.load(pth.as_ref())?;
+        Ok(self)
+    }
+    fn load(&mut self, pth: &Path) -> io::Result<()> {
+        let kvc = KVConfig::load(pth)?;
+        for (k, v) in kvc.mp {
+            self.put(k, v, Source::File(pth.to_path_buf()));
+        }
+        Ok(())
+    }
+    /// Env vars starting with `prefix_`, `APP_DB_HOST` sets `db.host`.
+    ///
+    /// `_` is the key separator, so a var also matches a key already set
+    /// that uses `_` itself: `APP_DB_MAX_CONN` sets `db.max_conn` if it exists.
+    /// Use `__` for a literal `_` in new keys.
+    pub fn env(&mut self, prefix: &str) -> &mut Self {
+        let vars: Vec<(String, String)> = std::env::vars_os()
+            .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+            .collect();
+        self.env_vars(prefix, vars)
+    }
+    fn env_vars(&mut self, prefix: &str, vars: Vec<(String, String)>) -> &mut Self {
+        let prefix = format!("{}_", prefix.trim_end_matches('_'));
+        let mut known: HashMap<String, String> = HashMap::new();
+        for k in self.cfg.mp.keys() {
+            known.insert(env_name(k), k.clone());
+        }
+        let mut vars: Vec<(String, String)> = vars
+            .into_iter()
+            .filter(|(k, _)| k.len() > prefix.len() && k.starts_with(&prefix))
+            .collect();
+        // stable result when two vars name the same key
+        vars.sort();
+        for (name, val) in vars {
+            let rest = &name[prefix.len()..];
+            let key = match known.get(&rest.to_ascii_uppercase()) {
+                Some(k) => k.clone(),
+                None => rest
+                    .to_ascii_lowercase()
+                    .split("__")
+                    .map(|v| v.replace('_', "."))
+                    .collect::<Vec<_>>()
+                    .join("_"),
+            };
+            self.put(key, val, Source::Env(name));
+        }
+        self
+    }
+    /// `--key=value` arguments, a lone `--flag` sets "true".
+    /// Other arguments are skipped, `--` ends the options.
+    pub fn args<I, T>(&mut self, args: I) -> io::Result<&mut Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        for arg in args {
+            let arg = arg.into();
+            if arg == "--" {
+                break;
+            }
+            let opt = match arg.strip_prefix("--") {
+                Some(v) => v,
+                None => continue,
+            };
+            let (key, val) = match opt.find('=') {
+                Some(i) => (&opt[..i], &opt[i + 1..]),
+                None => (opt, "true"),
+            };
+            if key.is_empty() {
+                return Err(crate::ioerr(
+                    format!("config arg '{}' has no key", arg),
+                    Some(io::ErrorKind::InvalidInput),
+                ));
+            }
+            self.put(key.to_string(), val.to_string(), Source::Arg);
+        }
+        Ok(self)
+    }
+}
+
+/// `db.max_conn` -> `DB_MAX_CONN`
+fn env_name(key: &str) -> String {
+    key.replace('.', "_").to_ascii_uppercase()
+}
@@ -0,0 +1,125 @@
+use std::{io, iter::Enumerate, str::Lines};
+
+use super::{parse_err, parse_value, parse_value_end};
+
+/// The line tokenizer behind `KVConfig` and `KVDocument`, so both read
+/// the same syntax. Yields one `Token` per logical line, a trailing odd `\`
+/// joins the next line.
+pub(super) struct Lexer<'a> {
+    lines: Enumerate<Lines<'a>>,
+    // `name.` of the current section
+    prefix: String,
+}
+
+pub(super) struct Token {
+    /// Number of the first line.
+    pub lineno: usize,
+    /// Original text, lines of a continuation joined with `\n`.
+    pub text: String,
+    /// The continuation joined into one line.
+    pub logical: String,
+    pub kind: io::Result<TokenKind>,
+}
+
+pub(super) enum TokenKind {
+    /// Blank or comment line.
+    Blank,
+    /// `[name]`, prefix is `name.` or empty for `[ ]`.
+    Section(String),
+    Include(String),
+    /// The key has the section prefix, `start..end` is the value text in `logical`.
+    Entry {
+        key: String,
+        val: String,
+        start: usize,
+        end: usize,
+    },
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(conts: &'a str) -> Self {
+        Self {
+            lines: conts.lines().enumerate(),
+            prefix: String::new(),
+        }
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Token;
+    fn next(&mut self) -> Option<Token> {
+        let (i, line) = self.lines.next()?;
+        let mut text = line.to_string();
+        let mut logical = line.to_string();
+        while logical.ends_with('\\')
+            && (logical.len() - logical.trim_end_matches('\\').len()) % 2 == 1
+        {
+            logical.pop();
+            match self.lines.next() {
+                Some((_, v)) => {
+                    text.push('\n');
+                    text.push_str(v);
+                    logical.push_str(v.trim_start());
+                }
+                None => break,
+            }
+        }
+        let kind = token(&logical, &mut self.prefix);
+        Some(Token {
+            lineno: i + 1,
+            text,
+            logical,
+            kind,
+        })
+    }
+}
+
+fn token(logical: &str, prefix: &mut String) -> io::Result<TokenKind> {
+    let line = logical.trim();
+    if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+        return Ok(TokenKind::Blank);
+    }
+    if let Some(v) = line.strip_prefix('[') {
+        let end = match v.find(']') {
+            Some(i) => i,
+            None => return Err(parse_err("unclosed '['")),
+        };
+        let rest = v[end + 1..].trim();
+        if !rest.is_empty() && !rest.starts_with('#') && !rest.starts_with(';') {
+            return Err(parse_err("unexpected text after section"));
+        }
+        let name = v[..end].trim();
+        *prefix = if name.is_empty() {
+            String::new()
+        } else {
+            format!("{}.", name)
+        };
+        return Ok(TokenKind::Section(prefix.clone()));
+    }
+    // `logical` only adds whitespace around `line`, so it has the same first '='
+    let eq = match logical.find('=') {
+        Some(i) => i,
+        None => {
+            return match line.strip_prefix("include") {
+                Some(v) if v.starts_with(char::is_whitespace) => {
+                    Ok(TokenKind::Include(parse_value(v.trim())?))
+                }
+                _ => Err(parse_err("missing '='")),
+            }
+        }
+    };
+    let key = logical[..eq].trim();
+    if key.is_empty() {
+        return Err(parse_err("empty key"));
+    }
+    let after = &logical[eq + 1..];
+    let vals = after.trim_start();
+    let start = eq + 1 + after.len() - vals.len();
+    let (val, n) = parse_value_end(vals.trim_end())?;
+    Ok(TokenKind::Entry {
+        key: format!("{}{}", prefix, key),
+        val,
+        start,
+        end: start + n,
+    })
+}
@@ -0,0 +1,333 @@
+use std::{
+    collections::{hash_map::Iter, HashMap},
+    io,
+    ops::Index,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+#[cfg(feature = "serdes")]
+pub use de::{de_duration, de_size, Error};
+pub use doc::KVDocument;
+pub use layered::{LayeredConfig, Source};
+pub use watch::ConfigWatcher;
+
+#[cfg(feature = "serdes")]
+mod de;
+mod doc;
+#[cfg(any(feature = "tomls", feature = "jsons"))]
+mod formats;
+mod layered;
+mod lexer;
+mod watch;
+
+/// Nested `include` limit, also stops include cycles.
+const INCLUDE_MAX: usize = 16;
+
+pub struct KVConfig {
+    mp: HashMap<String, String>,
+}
+
+impl KVConfig {
+    pub fn new() -> Self {
+        Self { mp: HashMap::new() }
+    }
+    /// Parses the file, `include` paths are relative to the including file.
+    ///
+    /// The key ends at the first `=`, so values may hold `=` but keys may not.
+    /// Before comments and quoting were added the split was at the last `=`,
+    /// `a=b=c` used to be key `a=b` and is now key `a` with value `b=c`.
+    ///
+    /// ```text
+    /// # comment, also `;`
+    /// name = plain value   # trailing comment
+    /// url = http://h/?a=1  # key `url`
+    /// quoted = "tab\t, quote \" and # kept"
+    /// raw = 'no \escapes'
+    /// long = first \
+    ///        second
+    /// [db]
+    /// host = 127.0.0.1     # key `db.host`
+    /// include more.conf
+    /// ```
+    pub fn from_file<P: Into<PathBuf>>(pth: P) -> io::Result<Self> {
+        let mut kvc = Self::new();
+        kvc.parse_file(&pth.into(), 0)?;
+        Ok(kvc)
+    }
+    /// Same syntax as `from_file`, errors are returned with their line number.
+    /// `include` paths are relative to the current directory.
+    pub fn parse(bts: &[u8]) -> io::Result<Self> {
+        let mut kvc = Self::new();
+        kvc.parses(bts, "<bytes>", None, 0, true)?;
+        Ok(kvc)
+    }
+    /// Lenient `parse`, lines that fail to parse are skipped.
+    pub fn from_bytes(bts: &[u8]) -> Self {
+        let mut kvc = Self::new();
+        let _ = kvc.parses(bts, "<bytes>", None, 0, false);
+        kvc
+    }
+
+    /// Picks the format by extension: `.toml` and `.json` with the
+    /// `tomls`/`jsons` features, anything else is read by `from_file`.
+    pub fn load<P: AsRef<Path>>(pth: P) -> io::Result<Self> {
+        let pth = pth.as_ref();
+        let ext = match pth.extension() {
+            Some(v) => v.to_string_lossy().to_ascii_lowercase(),
+            None => String::new(),
+        };
+        let name = pth.display().to_string();
+        match ext.as_str() {
+            #[cfg(feature = "tomls")]
+            "toml" => Ok(Self {
+                mp: formats::from_toml(&read_file(pth)?, &name)?,
+            }),
+            #[cfg(feature = "jsons")]
+            "json" => Ok(Self {
+                mp: formats::from_json(&read_file(pth)?, &name)?,
+            }),
+            #[cfg(not(feature = "tomls"))]
+            "toml" => Err(crate::ioerr(
+                format!("{}: toml needs feature `tomls`", name),
+                Some(io::ErrorKind::Unsupported),
+            )),
+            #[cfg(not(feature = "jsons"))]
+            "json" => Err(crate::ioerr(
+                format!("{}: json needs feature `jsons`", name),
+                Some(io::ErrorKind::Unsupported),
+            )),
+            _ => Self::from_file(pth),
+        }
+    }
+    /// TOML, nested keys joined with `.`.
+    #[cfg(feature = "tomls")]
+    pub fn from_toml(bts: &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            mp: formats::from_toml(bts, "<toml>")?,
+        })
+    }
+    /// JSON object, nested keys joined with `.`.
+    #[cfg(feature = "jsons")]
+    pub fn from_json(bts: &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            mp: formats::from_json(bts, "<json>")?,
+        })
+    }
+
+    fn parse_file(&mut self, pth: &Path, depth: usize) -> io::Result<()> {
+        let bts = read_file(pth)?;
+        self.parses(&bts, &pth.display().to_string(), pth.parent(), depth, true)
+    }
+    fn parses(
+        &mut self,
+        bts: &[u8],
+        name: &str,
+        dir: Option<&Path>,
+        depth: usize,
+        strict: bool,
+    ) -> io::Result<()> {
+        let conts = match std::str::from_utf8(bts) {
+            Ok(v) => std::borrow::Cow::Borrowed(v),
+            Err(e) if strict => {
+                return Err(crate::ioerr(
+                    format!("{}: not utf-8: {}", name, e),
+                    Some(io::ErrorKind::InvalidData),
+                ))
+            }
+            Err(_) => String::from_utf8_lossy(bts),
+        };
+        for tk in lexer::Lexer::new(&conts) {
+            let rst = match tk.kind {
+                Ok(lexer::TokenKind::Include(pth)) => self.include(&pth, dir, depth),
+                Ok(lexer::TokenKind::Entry { key, val, .. }) => {
+                    self.mp.insert(key, val);
+                    Ok(())
+                }
+                Ok(_) => Ok(()),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = rst {
+                if strict {
+                    return Err(crate::ioerr(
+                        format!("{}:{}: {}", name, tk.lineno, e),
+                        Some(e.kind()),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+    fn include(&mut self, pth: &str, dir: Option<&Path>, depth: usize) -> io::Result<()> {
+        if depth >= INCLUDE_MAX {
+            return Err(parse_err("include nested too deep"));
+        }
+        let pth = match dir {
+            Some(d) if Path::new(pth).is_relative() => d.join(pth),
+            _ => PathBuf::from(pth),
+        };
+        self.parse_file(&pth, depth + 1)
+    }
+
+    pub fn get<T: Into<String>>(&self, key: T) -> Option<&String> {
+        self.mp.get(&key.into())
+    }
+    pub fn geti<T: Into<String>>(&self, key: T) -> Option<i64> {
+        match self.mp.get(&key.into()) {
+            None => None,
+            Some(vs) => match vs.parse::<i64>() {
+                Ok(v) => Some(v),
+                Err(_) => None,
+            },
+        }
+    }
+    /// true/false, yes/no, on/off, 1/0, case insensitive.
+    pub fn get_bool<T: Into<String>>(&self, key: T) -> Option<bool> {
+        parse_bool(self.mp.get(&key.into())?)
+    }
+    pub fn get_f64<T: Into<String>>(&self, key: T) -> Option<f64> {
+        self.mp.get(&key.into())?.parse::<f64>().ok()
+    }
+    /// See `crate::parse_duration`.
+    pub fn get_duration<T: Into<String>>(&self, key: T) -> Option<Duration> {
+        crate::parse_duration(self.mp.get(&key.into())?).ok()
+    }
+    /// Bytes of "10MB" and the like, see `bytes::parse_human_readable`.
+    pub fn get_size<T: Into<String>>(&self, key: T) -> Option<u64> {
+        crate::bytes::parse_human_readable(self.mp.get(&key.into())?).ok()
+    }
+    /// Comma separated items, trimmed, empty ones dropped.
+    pub fn get_list<T: Into<String>>(&self, key: T) -> Option<Vec<String>> {
+        Some(split_list(self.mp.get(&key.into())?))
+    }
+    /// Deserializes the dotted keys as nested structs, `a.b.c` is field `c` of field `b` of field `a`.
+    #[cfg(feature = "serdes")]
+    pub fn deserialize<T: serde::de::DeserializeOwned>(&self) -> io::Result<T> {
+        de::from_map(&self.mp)
+            .map_err(|e| crate::ioerr(e.to_string(), Some(io::ErrorKind::InvalidData)))
+    }
+    pub fn set<T: Into<String>>(&mut self, key: T, val: T) {
+        self.mp.insert(key.into(), val.into());
+    }
+    pub fn iter(&self) -> Iter<String, String> {
+        self.mp.iter()
+    }
+    /// Keys sorted, see `KVDocument` to keep the layout of a file.
+    pub fn to_string(&self) -> String {
+        let mut kvs: Vec<_> = self.mp.iter().collect();
+        kvs.sort();
+        let mut cont = String::new();
+        for (k, v) in kvs {
+            cont.push_str(format!("{}={}\n", k, quote_value(v)).as_str());
+        }
+        cont
+    }
+}
+
+pub(crate) fn parse_bool(s: &str) -> Option<bool> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+pub(crate) fn split_list(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_string())
+        .collect()
+}
+
+fn read_file(pth: &Path) -> io::Result<Vec<u8>> {
+    std::fs::read(pth).map_err(|e| {
+        crate::ioerr(
+            format!("kv read {} err:{}", pth.display(), e),
+            Some(e.kind()),
+        )
+    })
+}
+
+fn parse_err(s: &str) -> io::Error {
+    crate::ioerr(s, Some(io::ErrorKind::InvalidData))
+}
+
+fn parse_value(s: &str) -> io::Result<String> {
+    Ok(parse_value_end(s)?.0)
+}
+/// Value part of a line: quoted, or plain up to a ` #`/` ;` comment.
+/// Also returns the length of the value text in `s`.
+fn parse_value_end(s: &str) -> io::Result<(String, usize)> {
+    let mut chars = s.chars();
+    let (val, end, rest) = match chars.next() {
+        Some('"') => {
+            let mut val = String::new();
+            loop {
+                match chars.next() {
+                    None => return Err(parse_err("unclosed '\"'")),
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => val.push('\n'),
+                        Some('t') => val.push('\t'),
+                        Some('r') => val.push('\r'),
+                        Some('0') => val.push('\0'),
+                        Some(c @ ('\\' | '"' | '\'' | '#' | ';')) => val.push(c),
+                        Some(c) => return Err(parse_err(&format!("unknown escape '\\{}'", c))),
+                        None => return Err(parse_err("unclosed '\"'")),
+                    },
+                    Some(c) => val.push(c),
+                }
+            }
+            let rest = chars.as_str();
+            (val, s.len() - rest.len(), rest)
+        }
+        Some('\'') => match chars.as_str().find('\'') {
+            Some(i) => (s[1..i + 1].to_string(), i + 2, &s[i + 2..]),
+            None => return Err(parse_err("unclosed \"'\"")),
+        },
+        _ => {
+            let mut end = s.len();
+            let mut prev = ' ';
+            for (i, c) in s.char_indices() {
+                if (c == '#' || c == ';') && prev.is_whitespace() && i > 0 {
+                    end = i;
+                    break;
+                }
+                prev = c;
+            }
+            let val = s[..end].trim_end();
+            return Ok((val.to_string(), val.len()));
+        }
+    };
+    let rest = rest.trim();
+    if !rest.is_empty() && !rest.starts_with('#') && !rest.starts_with(';') {
+        return Err(parse_err("unexpected text after quoted value"));
+    }
+    Ok((val, end))
+}
+
+/// Quotes `v` when `parse_value` would not read it back as is.
+fn quote_value(v: &str) -> String {
+    let plain = v.trim() == v
+        && !v.starts_with('"')
+        && !v.starts_with('\'')
+        && !v.ends_with('\\')
+        && !v.contains(|c: char| c == '#' || c == ';' || c.is_control());
+    if plain {
+        return v.to_string();
+    }
+    let mut rt = String::from("\"");
+    for c in v.chars() {
+        match c {
+            '\n' => rt.push_str("\\n"),
+            '\t' => rt.push_str("\\t"),
+            '\r' => rt.push_str("\\r"),
+            '\0' => rt.push_str("\\0"),
+            '\\' => rt.push_str("\\\\"),
+            '"' => rt.push_str("\\\""),
+            c => rt.push(c),
+        }
+    }
+    rt.push('"');
+    rt
+}
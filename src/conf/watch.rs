@@ -0,0 +1,166 @@
+use std::{
+    collections::BTreeSet,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use super::KVConfig;
+
+type SubFn = dyn Fn(&KVConfig, &[String]) + Send + Sync;
+type ErrFn = dyn Fn(&io::Error) + Send + Sync;
+
+/// Reloads a config file when its modification time or size changes,
+/// any format `KVConfig::load` reads.
+///
+/// Polls the file, so it works everywhere. Only the main file is watched,
+/// a change to an `include`d file is picked up with the next change of the main one.
+/// A file that fails to parse keeps the last good config and is reported to `on_error`.
+#[derive(Clone)]
+pub struct ConfigWatcher {
+    inner: Arc<Inner>,
+}
+struct Inner {
+    pth: PathBuf,
+    cfg: RwLock<Arc<KVConfig>>,
+    stat: Mutex<Option<(SystemTime, u64)>>,
+    subs: Mutex<Vec<Arc<SubFn>>>,
+    errfn: Mutex<Option<Arc<ErrFn>>>,
+}
+
+impl ConfigWatcher {
+    /// Loads the file, it must parse.
+    pub fn new<P: Into<PathBuf>>(pth: P) -> io::Result<Self> {
+        let pth = pth.into();
+        let stat = file_stat(&pth).ok();
+        let cfg = KVConfig::load(&pth)?;
+        Ok(Self {
+            inner: Arc::new(Inner {
+                pth,
+                cfg: RwLock::new(Arc::new(cfg)),
+                stat: Mutex::new(stat),
+                subs: Mutex::new(Vec::new()),
+                errfn: Mutex::new(None),
+            }),
+        })
+    }
+    pub fn path(&self) -> &Path {
+        &self.inner.pth
+    }
+    /// The current config, a reload swaps in a new one and leaves this one untouched.
+    pub fn config(&self) -> Arc<KVConfig> {
+        match self.inner.cfg.read() {
+            Ok(v) => v.clone(),
+            Err(e) => e.into_inner().clone(),
+        }
+    }
+    /// Called after each reload with the new config and the added, changed
+    /// and removed keys, sorted. Not called when nothing changed.
+    pub fn subscribe<F: Fn(&KVConfig, &[String]) + Send + Sync + 'static>(&self, f: F) {
+        match self.inner.subs.lock() {
+            Ok(mut v) => v.push(Arc::new(f)),
+            Err(e) => e.into_inner().push(Arc::new(f)),
+        }
+    }
+    /// Receives reload errors, they are printed to stderr without one.
+    pub fn on_error<F: Fn(&io::Error) + Send + Sync + 'static>(&self, f: F) {
+        match self.inner.errfn.lock() {
+            Ok(mut v) => *v = Some(Arc::new(f)),
+            Err(e) => *e.into_inner() = Some(Arc::new(f)),
+        }
+    }
+    fn report(&self, e: &io::Error) {
+        // called unlocked, it may call `on_error` itself
+        let f = match self.inner.errfn.lock() {
+            Ok(v) => v.clone(),
+            Err(e) => e.into_inner().clone(),
+        };
+        match f {
+            Some(f) => f(e),
+            None => eprintln!("ConfigWatcher {} err:{}", self.inner.pth.display(), e),
+        }
+    }
+
+    /// Reloads if the file changed since the last check,
+    /// returns the changed keys, empty when nothing changed.
+    pub fn check(&self) -> io::Result<Vec<String>> {
+        let stat = file_stat(&self.inner.pth)?;
+        {
+            let mut lkv = match self.inner.stat.lock() {
+                Ok(v) => v,
+                Err(e) => e.into_inner(),
+            };
+            if *lkv == Some(stat) {
+                return Ok(Vec::new());
+            }
+            // a file that fails to parse is not retried until it changes again
+            *lkv = Some(stat);
+        }
+        self.reload()
+    }
+    /// Reloads the file now, returns the changed keys.
+    pub fn reload(&self) -> io::Result<Vec<String>> {
+        let cfg = Arc::new(KVConfig::load(&self.inner.pth)?);
+        let old = self.config();
+        let chgs = changed_keys(&old, &cfg);
+        if chgs.is_empty() {
+            return Ok(chgs);
+        }
+        match self.inner.cfg.write() {
+            Ok(mut v) => *v = cfg.clone(),
+            Err(e) => *e.into_inner() = cfg.clone(),
+        }
+        // called unlocked, a subscriber may subscribe
+        let subs = match self.inner.subs.lock() {
+            Ok(v) => v.clone(),
+            Err(e) => e.into_inner().clone(),
+        };
+        for f in subs.iter() {
+            f(&cfg, &chgs[..]);
+        }
+        Ok(chgs)
+    }
+
+    /// Checks every `intvl` until `ctx` is done.
+    pub fn run(&self, ctx: &crate::Context, intvl: Duration) {
+        let intvl = intvl.max(Duration::from_millis(10));
+        let step = intvl.min(Duration::from_millis(100));
+        while !ctx.done() {
+            if let Err(e) = self.check() {
+                self.report(&e);
+            }
+            let mut slept = Duration::ZERO;
+            while slept < intvl && !ctx.done() {
+                std::thread::sleep(step);
+                slept += step;
+            }
+        }
+    }
+    /// `run` on a new thread.
+    pub fn start(&self, ctx: &crate::Context, intvl: Duration) -> std::thread::JoinHandle<()> {
+        let this = self.clone();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || this.run(&ctx, intvl))
+    }
+}
+
+fn file_stat(pth: &Path) -> io::Result<(SystemTime, u64)> {
+    let meta = std::fs::metadata(pth)?;
+    Ok((meta.modified()?, meta.len()))
+}
+
+fn changed_keys(old: &KVConfig, new: &KVConfig) -> Vec<String> {
+    let mut rt = BTreeSet::new();
+    for (k, v) in &new.mp {
+        if old.mp.get(k) != Some(v) {
+            rt.insert(k.clone());
+        }
+    }
+    for k in old.mp.keys() {
+        if !new.mp.contains_key(k) {
+            rt.insert(k.clone());
+        }
+    }
+    rt.into_iter().collect()
+}
@@ -1,12 +1,22 @@
 use std::{
+    ffi::c_void,
     ops::{Deref, DerefMut},
-    sync::Arc,
+    sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak},
 };
 
+/// Opaque pointer handed to C code by `ArcMut::into_handle`, holds one strong reference.
+///
+/// Give it back with `ArcMut::from_handle` exactly once to release it,
+/// `ArcMut::borrow_handle` uses it without releasing it.
+pub type ArcHandle = *mut c_void;
+
 pub struct ArcMut<T> {
-    ptrs: u64,
+    ptrs: *const T,
     inner: Arc<T>,
 }
+// same as Arc, the pointer is only an address of the Arc's value
+unsafe impl<T: Send + Sync> Send for ArcMut<T> {}
+unsafe impl<T: Send + Sync> Sync for ArcMut<T> {}
 impl<T> Clone for ArcMut<T> {
     fn clone(&self) -> Self {
         Self {
@@ -43,12 +53,35 @@ impl<T> ArcMut<T> {
             inner: inr,
         } */
     }
+    /// Mutable access without any synchronization.
+    ///
+    /// # Safety
+    /// Undefined behaviour when another clone reads or writes at the same time,
+    /// or a `&T` from `deref` is still alive. Prefer `ArcMut<Mutex<T>>`/
+    /// `ArcMut<RwLock<T>>` with `with_lock`/`read`/`write`, or `ArcSwap`.
     pub unsafe fn muts<'a>(&'a self) -> &'a mut T {
         &mut *(self.ptrs as *mut T)
     }
+    /// Address of the value, for logs and maps keyed by identity.
     pub fn ptr(&self) -> u64 {
+        self.ptrs as usize as u64
+    }
+    pub fn as_ptr(&self) -> *const T {
         self.ptrs
     }
+    /// Whether both point to the same value, like `Arc::ptr_eq`.
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        a.ptrs == b.ptrs
+    }
+    pub fn downgrade(&self) -> WeakMut<T> {
+        WeakMut {
+            ptrs: self.ptrs,
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+    pub fn weak_count(&self) -> usize {
+        Arc::weak_count(&self.inner)
+    }
 
     pub fn arc_count(&self) -> usize {
         Arc::strong_count(&self.inner)
@@ -80,11 +113,172 @@ impl<T> ArcMut<T> {
     }
 }
 
+impl<T> ArcMut<T> {
+    /// Moves this reference into an opaque handle for C code.
+    ///
+    /// ```
+    /// use ruisutil::{ArcHandle, ArcMut};
+    ///
+    /// let am = ArcMut::new(String::from("ruis"));
+    /// let h: ArcHandle = am.clone().into_handle();
+    /// // ... C code keeps `h` and calls back with it ...
+    /// let borrowed = unsafe { ArcMut::<String>::borrow_handle(h).unwrap() };
+    /// assert!(ArcMut::ptr_eq(&am, &borrowed));
+    /// drop(borrowed);
+    /// // done with the handle, releases its reference
+    /// drop(unsafe { ArcMut::<String>::from_handle(h).unwrap() });
+    /// assert_eq!(am.arc_count(), 1);
+    /// ```
+    pub fn into_handle(self) -> ArcHandle {
+        Arc::into_raw(self.inner) as *mut c_void
+    }
+    /// Takes the reference of `into_handle` back, `h` is invalid afterwards.
+    ///
+    /// # Safety
+    /// `h` must come from `ArcMut::<T>::into_handle` with the same `T`
+    /// and not have been given back yet.
+    pub unsafe fn from_handle(h: ArcHandle) -> std::io::Result<Self> {
+        Self::from_raw(h as *const T)
+    }
+    /// A new reference from a handle that stays valid.
+    ///
+    /// # Safety
+    /// Same as `from_handle`.
+    pub unsafe fn borrow_handle(h: ArcHandle) -> std::io::Result<Self> {
+        Self::from_raws(h as *const T)
+    }
+}
+
 impl<T> From<Arc<T>> for ArcMut<T> {
     fn from(inr: Arc<T>) -> Self {
         Self {
-            ptrs: (&*inr) as *const T as u64,
+            ptrs: Arc::as_ptr(&inr),
             inner: inr,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Weak reference of an `ArcMut`, does not keep the value alive.
+pub struct WeakMut<T> {
+    ptrs: *const T,
+    inner: Weak<T>,
+}
+unsafe impl<T: Send + Sync> Send for WeakMut<T> {}
+unsafe impl<T: Send + Sync> Sync for WeakMut<T> {}
+impl<T> Clone for WeakMut<T> {
+    fn clone(&self) -> Self {
+        Self {
+            ptrs: self.ptrs,
+            inner: self.inner.clone(),
+        }
+    }
+}
+impl<T> WeakMut<T> {
+    /// None once every `ArcMut` is dropped.
+    pub fn upgrade(&self) -> Option<ArcMut<T>> {
+        Some(ArcMut {
+            ptrs: self.ptrs,
+            inner: self.inner.upgrade()?,
+        })
+    }
+    pub fn arc_count(&self) -> usize {
+        self.inner.strong_count()
+    }
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        a.ptrs == b.ptrs
+    }
+}
+impl<T> ArcMut<Mutex<T>> {
+    pub fn new_mutex(t: T) -> Self {
+        Self::new(Mutex::new(t))
+    }
+    /// Locks, a lock poisoned by a panic is taken over.
+    /// Unlike the `Mutex::lock` of `Deref` it never fails.
+    pub fn lock_guard(&self) -> MutexGuard<'_, T> {
+        match self.inner.lock() {
+            Ok(v) => v,
+            Err(e) => e.into_inner(),
+        }
+    }
+    pub fn with_lock<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        f(&mut self.lock_guard())
+    }
+}
+
+impl<T> ArcMut<RwLock<T>> {
+    pub fn new_rw(t: T) -> Self {
+        Self::new(RwLock::new(t))
+    }
+    /// Shared lock, a lock poisoned by a panic is taken over.
+    pub fn read_guard(&self) -> RwLockReadGuard<'_, T> {
+        match self.inner.read() {
+            Ok(v) => v,
+            Err(e) => e.into_inner(),
+        }
+    }
+    /// Exclusive lock, a lock poisoned by a panic is taken over.
+    pub fn write_guard(&self) -> RwLockWriteGuard<'_, T> {
+        match self.inner.write() {
+            Ok(v) => v,
+            Err(e) => e.into_inner(),
+        }
+    }
+    pub fn with_lock<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        f(&mut self.write_guard())
+    }
+}
+
+impl<T> ArcMut<ArcSwap<T>> {
+    pub fn new_swap(t: T) -> Self {
+        Self::new(ArcSwap::new(t))
+    }
+}
+
+/// Read-mostly value replaced as a whole: readers take a cheap `Arc` snapshot
+/// with `load` that later `store`s do not change.
+///
+/// The lock is only held to clone or replace the `Arc`, never while
+/// the value is read or built.
+pub struct ArcSwap<T> {
+    cur: RwLock<Arc<T>>,
+}
+
+impl<T> ArcSwap<T> {
+    pub fn new(t: T) -> Self {
+        Self {
+            cur: RwLock::new(Arc::new(t)),
+        }
+    }
+    pub fn load(&self) -> Arc<T> {
+        match self.cur.read() {
+            Ok(v) => v.clone(),
+            Err(e) => e.into_inner().clone(),
+        }
+    }
+    pub fn store(&self, t: T) {
+        self.swap(t);
+    }
+    /// Stores `t`, returns the previous value.
+    pub fn swap(&self, t: T) -> Arc<T> {
+        let t = Arc::new(t);
+        match self.cur.write() {
+            Ok(mut v) => std::mem::replace(&mut *v, t),
+            Err(e) => std::mem::replace(&mut *e.into_inner(), t),
+        }
+    }
+    /// Stores `f(current)`, retried when another update got in between.
+    pub fn update<F: FnMut(&T) -> T>(&self, mut f: F) -> Arc<T> {
+        loop {
+            let old = self.load();
+            let new = Arc::new(f(&old));
+            let mut lkv = match self.cur.write() {
+                Ok(v) => v,
+                Err(e) => e.into_inner(),
+            };
+            if Arc::ptr_eq(&lkv, &old) {
+                *lkv = new.clone();
+                return new;
+            }
+        }
+    }
+}
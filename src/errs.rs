@@ -1,34 +1,347 @@
-use std::fmt::Debug;
+use std::{backtrace::Backtrace, collections::BTreeMap, error, fmt, io, sync::Arc};
 
+/// Error kinds of this crate, `Error::error_kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Plain io error, see `Error::kind`.
+    Io,
+    /// A `Context` was stopped or cancelled.
+    ContextCanceled,
+    Timeout,
+    /// The peer sent something malformed.
+    Protocol,
+    /// A size or count limit was exceeded.
+    Limit,
+}
+
+impl ErrorKind {
+    /// The `io::ErrorKind` errors of this kind convert to.
+    pub fn io_kind(&self) -> io::ErrorKind {
+        match self {
+            ErrorKind::Io => io::ErrorKind::Other,
+            ErrorKind::ContextCanceled => io::ErrorKind::Interrupted,
+            ErrorKind::Timeout => io::ErrorKind::TimedOut,
+            ErrorKind::Protocol => io::ErrorKind::InvalidData,
+            ErrorKind::Limit => io::ErrorKind::Other,
+        }
+    }
+}
+
+/// Error with a kind, context messages and an optional cause.
+///
+/// Displays as `outer context: inner context: msg`. An error converted from
+/// another one with `From`/`wrap` and no message of its own is transparent:
+/// `Display` and `source` are those of the original.
+/// Backtraces are opt-in with `with_backtrace`, errors made in hot loops
+/// like `asyncs::timeouts` stay cheap.
 #[derive(Debug, Clone)]
 pub struct Error {
     pub msg: String,
-    pub kind: std::io::ErrorKind,
+    pub kind: io::ErrorKind,
+    ekind: Option<ErrorKind>,
+    code: i32,
+    details: BTreeMap<String, String>,
+    // innermost first
+    ctxs: Vec<String>,
+    source: Option<Arc<dyn error::Error + Send + Sync>>,
+    bt: Option<Arc<Backtrace>>,
 }
 
 impl Error {
-    pub fn new<T: Into<String>>(msg: T, kind: std::io::ErrorKind) -> Self {
+    pub fn new<T: Into<String>>(msg: T, kind: io::ErrorKind) -> Self {
         Self {
             msg: msg.into(),
             kind,
+            ekind: None,
+            code: 0,
+            details: BTreeMap::new(),
+            ctxs: Vec::new(),
+            source: None,
+            bt: None,
         }
     }
     pub fn news<T: Into<String>>(msg: T) -> Self {
-        Self::new(msg, std::io::ErrorKind::Other)
+        Self::new(msg, io::ErrorKind::Other)
+    }
+    pub fn with_kind<T: Into<String>>(kind: ErrorKind, msg: T) -> Self {
+        let mut rt = Self::new(msg, kind.io_kind());
+        rt.ekind = Some(kind);
+        rt
+    }
+    /// Transparent wrapper of `e`, kept as is for `get_ref`/`downcast_ref`.
+    pub fn wrap<E: error::Error + Send + Sync + 'static>(e: E, kind: io::ErrorKind) -> Self {
+        Self::new("", kind).with_source(e)
     }
-    pub fn kind(&self) -> std::io::ErrorKind {
+    /// Sets the cause returned by `source`.
+    pub fn with_source<E: error::Error + Send + Sync + 'static>(mut self, e: E) -> Self {
+        self.source = Some(Arc::new(e));
+        self
+    }
+    /// Captures a backtrace of the caller, whatever `RUST_BACKTRACE` says.
+    pub fn with_backtrace(mut self) -> Self {
+        self.bt = Some(Arc::new(Backtrace::force_capture()));
+        self
+    }
+    /// Application defined code, 0 when unset.
+    pub fn with_code(mut self, code: i32) -> Self {
+        self.code = code;
+        self
+    }
+    /// Adds a key/value detail, like the field that failed validation.
+    pub fn with_detail<K: Into<String>, V: Into<String>>(mut self, key: K, val: V) -> Self {
+        self.details.insert(key.into(), val.into());
+        self
+    }
+    /// Adds a message in front, `.context("opening log file")`.
+    pub fn context<C: Into<String>>(mut self, ctx: C) -> Self {
+        self.ctxs.push(ctx.into());
+        self
+    }
+
+    pub fn kind(&self) -> io::ErrorKind {
         self.kind
     }
+    pub fn error_kind(&self) -> ErrorKind {
+        match self.ekind {
+            Some(v) => v,
+            None => match self.kind {
+                io::ErrorKind::TimedOut => ErrorKind::Timeout,
+                _ => ErrorKind::Io,
+            },
+        }
+    }
+    pub fn code(&self) -> i32 {
+        self.code
+    }
+    pub fn details(&self) -> &BTreeMap<String, String> {
+        &self.details
+    }
+    pub fn detail(&self, key: &str) -> Option<&str> {
+        self.details.get(key).map(|v| v.as_str())
+    }
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.bt.as_deref()
+    }
+    /// The wrapped error, see `wrap` and `with_source`.
+    pub fn get_ref(&self) -> Option<&(dyn error::Error + Send + Sync + 'static)> {
+        self.source.as_deref()
+    }
+    pub fn downcast_ref<E: error::Error + 'static>(&self) -> Option<&E> {
+        self.get_ref()?.downcast_ref::<E>()
+    }
 }
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.msg)
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for v in self.ctxs.iter().rev() {
+            write!(f, "{}: ", v)?;
+        }
+        match &self.source {
+            Some(e) if self.msg.is_empty() => write!(f, "{}", e),
+            _ => write!(f, "{}", self.msg),
+        }
     }
 }
 
-impl From<Error> for std::io::Error {
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        let e = self.source.as_deref()?;
+        if self.msg.is_empty() {
+            e.source()
+        } else {
+            Some(e)
+        }
+    }
+}
+
+impl From<Error> for io::Error {
     fn from(value: Error) -> Self {
-        std::io::Error::new(value.kind, value.msg)
+        io::Error::new(value.kind, value)
+    }
+}
+
+impl From<io::Error> for Error {
+    /// Unwraps an `Error` that went through `io::Error`, wraps anything else.
+    fn from(e: io::Error) -> Self {
+        if let Some(v) = e.get_ref().and_then(|v| v.downcast_ref::<Error>()) {
+            return v.clone();
+        }
+        let kind = e.kind();
+        Self::wrap(e, kind)
+    }
+}
+
+/// `.context()` for results, converting the error to `Error`.
+pub trait ResultExt<T> {
+    fn context<C: Into<String>>(self, ctx: C) -> Result<T, Error>;
+    /// Like `context`, the message is only built on error.
+    fn with_context<C: Into<String>, F: FnOnce() -> C>(self, f: F) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for Result<T, E> {
+    fn context<C: Into<String>>(self, ctx: C) -> Result<T, Error> {
+        self.map_err(|e| e.into().context(ctx))
+    }
+    fn with_context<C: Into<String>, F: FnOnce() -> C>(self, f: F) -> Result<T, Error> {
+        self.map_err(|e| e.into().context(f()))
+    }
+}
+
+const WIRE_VERSION: u8 = 1;
+
+// wire tags, the index is sent: append only, never reorder.
+// Kinds not listed here are sent by name.
+const IO_KINDS: [io::ErrorKind; 20] = [
+    io::ErrorKind::Other,
+    io::ErrorKind::NotFound,
+    io::ErrorKind::PermissionDenied,
+    io::ErrorKind::ConnectionRefused,
+    io::ErrorKind::ConnectionReset,
+    io::ErrorKind::ConnectionAborted,
+    io::ErrorKind::NotConnected,
+    io::ErrorKind::AddrInUse,
+    io::ErrorKind::AddrNotAvailable,
+    io::ErrorKind::BrokenPipe,
+    io::ErrorKind::AlreadyExists,
+    io::ErrorKind::WouldBlock,
+    io::ErrorKind::InvalidInput,
+    io::ErrorKind::InvalidData,
+    io::ErrorKind::TimedOut,
+    io::ErrorKind::WriteZero,
+    io::ErrorKind::Interrupted,
+    io::ErrorKind::UnexpectedEof,
+    io::ErrorKind::Unsupported,
+    io::ErrorKind::OutOfMemory,
+];
+// io kind not in `IO_KINDS`, followed by its name
+const IO_KIND_NAMED: u8 = 0xff;
+/// Detail holding the name of an io kind `decode` did not know.
+pub const DETAIL_IO_KIND: &str = "io_kind";
+const KINDS: [ErrorKind; 5] = [
+    ErrorKind::Io,
+    ErrorKind::ContextCanceled,
+    ErrorKind::Timeout,
+    ErrorKind::Protocol,
+    ErrorKind::Limit,
+];
+const KIND_NONE: u8 = 0xff;
+
+impl Error {
+    /// Binary form for sending an error to a peer, e.g. as a `Message` body.
+    ///
+    /// Keeps the kinds, code, details and the displayed message (contexts
+    /// included), not the source or backtrace. Big endian:
+    /// `version:u8 kind:u8 io_kind:u8 [io_name:str] code:i32 msg:str n:u16 (key:str val:str)*`,
+    /// `str` is a `u32` length and utf-8 bytes. An io kind without a tag is
+    /// sent as 0xff and its name.
+    pub fn encode(&self) -> Vec<u8> {
+        let msg = self.to_string();
+        let mut buf = Vec::with_capacity(16 + msg.len());
+        buf.push(WIRE_VERSION);
+        buf.push(match self.ekind {
+            Some(k) => KINDS.iter().position(|v| *v == k).unwrap_or(0) as u8,
+            None => KIND_NONE,
+        });
+        match IO_KINDS.iter().position(|v| *v == self.kind) {
+            Some(i) => buf.push(i as u8),
+            None => {
+                buf.push(IO_KIND_NAMED);
+                put_str(&mut buf, &self.kind.to_string());
+            }
+        }
+        buf.extend_from_slice(&self.code.to_be_bytes());
+        put_str(&mut buf, &msg);
+        let n = self.details.len().min(u16::MAX as usize);
+        buf.extend_from_slice(&(n as u16).to_be_bytes());
+        for (k, v) in self.details.iter().take(n) {
+            put_str(&mut buf, k);
+            put_str(&mut buf, v);
+        }
+        buf
+    }
+    /// Reads what `encode` wrote, a malformed input is a `Protocol` error.
+    /// An io kind this build does not know reads as `Other`, with its name
+    /// in the `DETAIL_IO_KIND` detail when the peer sent one.
+    pub fn decode(bts: &[u8]) -> io::Result<Self> {
+        let mut rd = WireReader { bts };
+        let ver = rd.take(1)?[0];
+        if ver != WIRE_VERSION {
+            return Err(wire_err(format!("unknown version {}", ver)));
+        }
+        let hd = rd.take(2)?;
+        let (kind, iokind) = (hd[0], hd[1]);
+        let (iokind, ioname) = match iokind {
+            IO_KIND_NAMED => {
+                let name = rd.string()?;
+                match IO_KINDS.iter().find(|v| v.to_string() == name) {
+                    Some(k) => (*k, None),
+                    None => (io::ErrorKind::Other, Some(name)),
+                }
+            }
+            v => match IO_KINDS.get(v as usize) {
+                Some(k) => (*k, None),
+                None => (io::ErrorKind::Other, None),
+            },
+        };
+        let hd = rd.take(4)?;
+        let code = i32::from_be_bytes([hd[0], hd[1], hd[2], hd[3]]);
+        let msg = rd.string()?;
+        let hd = rd.take(2)?;
+        let n = u16::from_be_bytes([hd[0], hd[1]]);
+        let mut rt = Self::new(msg, iokind);
+        rt.code = code;
+        rt.ekind = match kind {
+            KIND_NONE => None,
+            v => match KINDS.get(v as usize) {
+                Some(k) => Some(*k),
+                None => return Err(wire_err(format!("unknown kind {}", v))),
+            },
+        };
+        for _ in 0..n {
+            let k = rd.string()?;
+            let v = rd.string()?;
+            rt.details.insert(k, v);
+        }
+        if let Some(v) = ioname {
+            rt.details.insert(DETAIL_IO_KIND.to_string(), v);
+        }
+        if !rd.bts.is_empty() {
+            return Err(wire_err(format!("{} trailing bytes", rd.bts.len())));
+        }
+        Ok(rt)
+    }
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u32).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+fn wire_err(msg: String) -> io::Error {
+    Error::with_kind(ErrorKind::Protocol, format!("error decode: {}", msg)).into()
+}
+
+struct WireReader<'a> {
+    bts: &'a [u8],
+}
+impl<'a> WireReader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.bts.len() < n {
+            return Err(wire_err(format!(
+                "need {} bytes, {} left",
+                n,
+                self.bts.len()
+            )));
+        }
+        let (rt, rest) = self.bts.split_at(n);
+        self.bts = rest;
+        Ok(rt)
+    }
+    fn string(&mut self) -> io::Result<String> {
+        let hd = self.take(4)?;
+        let n = u32::from_be_bytes([hd[0], hd[1], hd[2], hd[3]]) as usize;
+        match std::str::from_utf8(self.take(n)?) {
+            Ok(v) => Ok(v.to_string()),
+            Err(e) => Err(wire_err(e.to_string())),
+        }
     }
 }
@@ -3,29 +3,103 @@ use std::{
     io::{self, Read, Seek, Write},
     path::Path,
     sync::{
-        atomic::{AtomicUsize, Ordering},
-        Mutex, MutexGuard,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, RwLock,
     },
-    time::Duration,
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
 };
 
 #[derive(Clone)]
 pub struct FileSpliter {
     inner: crate::ArcMut<Inner>,
 }
+#[derive(Clone)]
 pub struct Config {
     pub maxbuf: usize,
     pub flpath: String,
-    pub flsize: u32,  // file size limit(KB)
-    pub flcount: u32, // file count
+    pub flsize: u32,  // file size limit(KB), 0 means none when `period` is set
+    pub flcount: u32, // file count, 0 means no limit when `period` is set
+    /// Rotates at the start of every hour/day too, rotated files are named
+    /// `flpath.<tmfmt>` and `flpath.<tmfmt>.N` when one period has several.
+    pub period: Period,
+    pub tmfmt: String, // strftime format, defaults to "%Y-%m-%d" or "%Y-%m-%d-%H"
+    pub maxage: Duration, // removes rotated files older than this, zero keeps them
+    pub gzip: bool,    // compresses rotated files in the background, `flpath.1.gz`
+    pub overflow: Overflow, // what `push` does when `maxbuf` chunks are queued
+    pub fsync: Fsync,
+    pub fsync_errors: bool, // `log::Logger` syncs after error records
+}
+/// When written data is `sync_data`ed, besides `push_sync`, `flush_and_wait` and `shutdown`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fsync {
+    Never,
+    Bytes(u64),
+    Interval(Duration),
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    DropOldest,
+    DropNewest,
+    /// Waits for the writer, until the spliter is stopped.
+    Block,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Period {
+    Never,
+    Hourly,
+    Daily,
+}
+/// Coalesced queued chunks per write.
+const BATCH_MAX: usize = 64 * 1024;
+
+type ErrFn = dyn Fn(&io::Error) + Send + Sync;
+
+/// Rotated files waiting for compression, done in order by one thread
+/// that exits when the queue is empty.
+struct GzQueue {
+    jobs: std::collections::VecDeque<GzJob>,
+    running: bool,
+    thr: Option<JoinHandle<()>>,
 }
+struct GzJob {
+    cfg: Arc<Config>,
+    pth: String,
+    // `pth` is a staged file still to be shifted in as `flpath.1`
+    shift: bool,
+}
+
 struct Inner {
     ctx: crate::Context,
-    cfg: Config,
+    cfg: RwLock<Arc<Config>>,
     buf: Mutex<crate::ListDequeMax<bytes::Bytes>>,
     wkr: crate::sync::Waker,
+    wkr_space: crate::sync::Waker,
+    dropped: AtomicU64,
+    // chunks queued, chunks written or dropped after being queued, `handled` at the last sync,
+    // `pushed` of the last chunk a `push_sync`/`flush_and_wait` waits to be synced
+    pushed: AtomicU64,
+    handled: AtomicU64,
+    synced: AtomicU64,
+    sync_upto: AtomicU64,
+    unsynced: AtomicU64,
+    synctm: Mutex<Instant>,
+    wkr_synced: crate::sync::Waker,
+    // running `run`/`run_async` loops, `flush_and_wait` fails without one
+    writers: AtomicUsize,
+    // task of `run_async`, a plain `Waker` keeps the spliter `Send`
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    awaker: Mutex<Option<std::task::Waker>>,
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    anotified: AtomicBool,
+    errfn: Mutex<Option<Arc<ErrFn>>>,
     flfd: Mutex<Option<File>>,
     flln: AtomicUsize,
+    // when the open file was started, for time based rotation
+    fltm: Mutex<Option<SystemTime>>,
+    gzq: Mutex<GzQueue>,
+    gzseq: AtomicU64,
+    thr: Mutex<Option<JoinHandle<()>>>,
 }
 impl Default for Config {
     fn default() -> Self {
@@ -34,30 +108,81 @@ impl Default for Config {
             flpath: String::new(),
             flsize: 0,
             flcount: 0,
+            period: Period::Never,
+            tmfmt: String::new(),
+            maxage: Duration::ZERO,
+            gzip: false,
+            overflow: Overflow::DropOldest,
+            fsync: Fsync::Never,
+            fsync_errors: false,
         }
     }
 }
-impl FileSpliter {
-    pub fn new(ctx: &crate::Context, mut cg: Config) -> Self {
-        if cg.maxbuf <= 0 {
-            cg.maxbuf = 20;
+impl Config {
+    fn normalize(mut self) -> Self {
+        if self.maxbuf <= 0 {
+            self.maxbuf = 20;
         }
-        if cg.flsize <= 0 {
-            cg.flsize = 1024;
+        if self.period == Period::Never {
+            if self.flsize <= 0 {
+                self.flsize = 1024;
+            }
+            if self.flcount <= 0 {
+                self.flcount = 1;
+            }
+        } else if self.tmfmt.is_empty() {
+            self.tmfmt = match self.period {
+                Period::Hourly => "%Y-%m-%d-%H",
+                _ => "%Y-%m-%d",
+            }
+            .to_string();
         }
-        if cg.flcount <= 0 {
-            cg.flcount = 1;
+        self
+    }
+    fn period_key(&self, tm: SystemTime) -> String {
+        match self.period {
+            Period::Never => String::new(),
+            Period::Hourly => crate::strftime(tm, "%Y%m%d%H"),
+            Period::Daily => crate::strftime(tm, "%Y%m%d"),
         }
+    }
+}
+impl FileSpliter {
+    pub fn new(ctx: &crate::Context, cg: Config) -> Self {
+        let cg = cg.normalize();
         let maxbuf = cg.maxbuf;
         let ctxs = crate::Context::background(Some(ctx.clone()));
         Self {
             inner: crate::ArcMut::new(Inner {
                 ctx: ctxs.clone(),
-                cfg: cg,
+                cfg: RwLock::new(Arc::new(cg)),
                 buf: Mutex::new(crate::ListDequeMax::new(maxbuf)),
                 wkr: crate::sync::Waker::new(&ctxs),
+                wkr_space: crate::sync::Waker::new(&ctxs),
+                dropped: AtomicU64::new(0),
+                pushed: AtomicU64::new(0),
+                handled: AtomicU64::new(0),
+                synced: AtomicU64::new(0),
+                sync_upto: AtomicU64::new(0),
+                unsynced: AtomicU64::new(0),
+                synctm: Mutex::new(Instant::now()),
+                wkr_synced: crate::sync::Waker::new(&ctxs),
+                writers: AtomicUsize::new(0),
+                #[cfg(any(feature = "asyncs", feature = "tokios"))]
+                awaker: Mutex::new(None),
+                #[cfg(any(feature = "asyncs", feature = "tokios"))]
+                anotified: AtomicBool::new(false),
+                errfn: Mutex::new(None),
                 flfd: Mutex::new(None),
                 flln: AtomicUsize::new(0),
+                fltm: Mutex::new(None),
+                gzq: Mutex::new(GzQueue {
+                    jobs: std::collections::VecDeque::new(),
+                    running: false,
+                    thr: None,
+                }),
+                gzseq: AtomicU64::new(0),
+                thr: Mutex::new(None),
             }),
         }
     }
@@ -65,14 +190,231 @@ impl FileSpliter {
     pub fn stop(&self) {
         self.inner.ctx.stop();
         self.inner.wkr.close();
+        self.inner.wkr_space.close();
+        self.inner.wkr_synced.close();
+        #[cfg(any(feature = "asyncs", feature = "tokios"))]
+        self.wake_async();
+    }
+    fn notify_writer(&self) {
+        self.inner.wkr.notify_all();
+        #[cfg(any(feature = "asyncs", feature = "tokios"))]
+        self.wake_async();
+    }
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    fn wake_async(&self) {
+        self.inner.anotified.store(true, Ordering::SeqCst);
+        let wk = match self.inner.awaker.lock() {
+            Ok(mut v) => v.take(),
+            Err(e) => e.into_inner().take(),
+        };
+        if let Some(v) = wk {
+            v.wake();
+        }
+    }
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    fn poll_notified(&self, cx: &mut std::task::Context<'_>) -> std::task::Poll<io::Result<()>> {
+        if self.inner.anotified.swap(false, Ordering::SeqCst) {
+            return std::task::Poll::Ready(Ok(()));
+        }
+        match self.inner.awaker.lock() {
+            Ok(mut v) => *v = Some(cx.waker().clone()),
+            Err(e) => *e.into_inner() = Some(cx.waker().clone()),
+        }
+        // a notify between the check and storing the waker
+        if self.inner.anotified.swap(false, Ordering::SeqCst) {
+            return std::task::Poll::Ready(Ok(()));
+        }
+        std::task::Poll::Pending
+    }
+
+    /// Receives write, rotation and compression errors, they are printed to stderr without one.
+    pub fn on_error<F: Fn(&io::Error) + Send + Sync + 'static>(&self, f: F) {
+        match self.inner.errfn.lock() {
+            Ok(mut v) => *v = Some(Arc::new(f)),
+            Err(e) => *e.into_inner() = Some(Arc::new(f)),
+        }
+    }
+    fn report(&self, e: &io::Error) {
+        // called unlocked, so the callback may replace itself or report again
+        let f = match self.inner.errfn.lock() {
+            Ok(v) => v.clone(),
+            Err(e) => e.into_inner().clone(),
+        };
+        match f {
+            Some(f) => f(e),
+            None => eprintln!("FileSpliter err:{}", e),
+        }
+    }
+    /// Chunks lost to the overflow policy.
+    pub fn dropped(&self) -> u64 {
+        self.inner.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn config(&self) -> Arc<Config> {
+        match self.inner.cfg.read() {
+            Ok(v) => v.clone(),
+            Err(e) => e.into_inner().clone(),
+        }
+    }
+    /// Takes effect from the next write, the current file is closed and
+    /// the one at the new `flpath` is opened.
+    pub fn set_config(&self, cg: Config) {
+        let cg = cg.normalize();
+        let mut flfd = match self.inner.flfd.lock() {
+            Ok(v) => v,
+            Err(e) => e.into_inner(),
+        };
+        // queued chunks drain through the writer, pushes past the new max go by `overflow`
+        if let Ok(mut lkv) = self.inner.buf.lock() {
+            lkv.set_max_keep(cg.maxbuf);
+        }
+        match self.inner.cfg.write() {
+            Ok(mut v) => *v = Arc::new(cg),
+            Err(e) => *e.into_inner() = Arc::new(cg),
+        }
+        *flfd = None;
+        self.inner.flln.store(0, Ordering::SeqCst);
+    }
+
+    /// Runs `run` in a thread that `shutdown` joins, fails right away on an unusable path.
+    pub fn start(&self) -> io::Result<()> {
+        self.check_path()?;
+        let mut thr = match self.inner.thr.lock() {
+            Ok(v) => v,
+            Err(e) => e.into_inner(),
+        };
+        if thr.is_some() {
+            return Err(crate::ioerr("FileSpliter is started", None));
+        }
+        let flspt = self.clone();
+        // counted before the thread runs, a `flush_and_wait` right after waits for it
+        self.inner.writers.fetch_add(1, Ordering::SeqCst);
+        *thr = Some(std::thread::spawn(move || {
+            let _dfs = crate::defers(|| {
+                flspt.inner.writers.fetch_sub(1, Ordering::SeqCst);
+            });
+            if let Err(e) = flspt.runs() {
+                flspt.report(&e);
+            }
+        }));
+        Ok(())
+    }
+    /// Stops and joins the thread of `start`, then writes what is still
+    /// buffered and closes the file.
+    pub fn shutdown(&self) {
+        self.stop();
+        let thr = match self.inner.thr.lock() {
+            Ok(mut v) => v.take(),
+            Err(e) => e.into_inner().take(),
+        };
+        if let Some(v) = thr {
+            let _ = v.join();
+        }
+        let ctx = crate::Context::with_timeout(None, Duration::from_secs(5));
+        while !ctx.done() {
+            match self.pops() {
+                Some((v, n)) => {
+                    self.appends(&ctx, &v[..]);
+                    self.inner.handled.fetch_add(n, Ordering::SeqCst);
+                }
+                None => break,
+            }
+        }
+        self.syncs(true);
+        if let Ok(mut lkv) = self.inner.flfd.lock() {
+            *lkv = None;
+            self.inner.flln.store(0, Ordering::SeqCst);
+        }
+        self.wait_gzip();
     }
 
     pub fn run(&self) -> io::Result<()> {
+        self.inner.writers.fetch_add(1, Ordering::SeqCst);
+        let _dfs = crate::defers(|| {
+            self.inner.writers.fetch_sub(1, Ordering::SeqCst);
+        });
+        self.runs()
+    }
+    fn runs(&self) -> io::Result<()> {
+        self.check_path()?;
+        self.recover();
+        while !self.inner.ctx.done() {
+            if let Some((v, n)) = self.pops() {
+                self.appends(&self.inner.ctx, &v[..]);
+                self.inner.handled.fetch_add(n, Ordering::SeqCst);
+            } else {
+                self.inner.wkr.wait_timeout(Duration::from_millis(100));
+            }
+            self.syncs(false);
+        }
+        if let Ok(mut lkv) = self.inner.flfd.lock() {
+            *lkv = None;
+            self.inner.flln.store(0, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+    /// `run` on the async runtime, file io goes through `spawn_blocking_io`.
+    /// Ends when `ctx` is cancelled or the spliter is stopped.
+    ///
+    /// Not `Send` with `asyncs`, that `Context` holds an `Rc` token: drive it on
+    /// the current task (`join`, `spawn_local`) there.
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    pub async fn run_async(&self, ctx: &crate::asyncs::Context) -> io::Result<()> {
+        self.inner.writers.fetch_add(1, Ordering::SeqCst);
+        let _dfs = crate::defers(|| {
+            self.inner.writers.fetch_sub(1, Ordering::SeqCst);
+        });
+        self.check_path()?;
+        let this = self.clone();
+        crate::asyncs::spawn_blocking_io(move || {
+            this.recover();
+            Ok(())
+        })
+        .await?;
+        while !self.inner.ctx.done() && ctx.done_err().is_ok() {
+            let pops = self.pops();
+            let idle = pops.is_none();
+            if idle && !self.sync_pending() {
+                // `ctx` is checked by the loop at least every 100ms
+                let _ = crate::asyncs::timeouts(
+                    Duration::from_millis(100),
+                    crate::asyncs::poll_fn(|cx| self.poll_notified(cx)),
+                )
+                .await;
+                continue;
+            }
+            let this = self.clone();
+            crate::asyncs::spawn_blocking_io(move || {
+                if let Some((v, n)) = pops {
+                    this.appends(&this.inner.ctx, &v[..]);
+                    this.inner.handled.fetch_add(n, Ordering::SeqCst);
+                }
+                this.syncs(false);
+                Ok(())
+            })
+            .await?;
+        }
+        let this = self.clone();
+        crate::asyncs::spawn_blocking_io(move || {
+            if let Ok(mut lkv) = this.inner.flfd.lock() {
+                *lkv = None;
+                this.inner.flln.store(0, Ordering::SeqCst);
+            }
+            Ok(())
+        })
+        .await
+    }
+    #[cfg(any(feature = "asyncs", feature = "tokios"))]
+    fn sync_pending(&self) -> bool {
+        self.sync_requested() || self.sync_due()
+    }
+    fn check_path(&self) -> io::Result<()> {
         {
-            if self.inner.cfg.flpath.is_empty() {
+            let cfg = self.config();
+            if cfg.flpath.is_empty() {
                 return Err(crate::ioerr("file path is empty", None));
             }
-            let pth = Path::new(self.inner.cfg.flpath.as_str());
+            let pth = Path::new(cfg.flpath.as_str());
             if pth.exists() && pth.is_dir() {
                 return Err(crate::ioerr("file path is err", None));
             }
@@ -83,30 +425,106 @@ impl FileSpliter {
                 std::fs::create_dir_all(o);
             } */
         }
-        while !self.inner.ctx.done() {
-            let out = {
-                if let Ok(mut lkv) = self.inner.buf.lock() {
-                    lkv.pop()
-                } else {
-                    std::thread::sleep(Duration::from_millis(10));
-                    continue;
-                }
+        Ok(())
+    }
+    /// Takes the queued chunks, joined into one up to `BATCH_MAX`, and their count.
+    fn pops(&self) -> Option<(bytes::Bytes, u64)> {
+        let mut n = 1;
+        let rt = {
+            let mut lkv = match self.inner.buf.lock() {
+                Ok(v) => v,
+                Err(e) => e.into_inner(),
             };
-            if let Some(v) = out {
-                self.appends(&v[..]);
+            let first = lkv.pop()?;
+            if lkv.len() == 0 || first.len() >= BATCH_MAX {
+                first
             } else {
-                self.inner.wkr.wait_timeout(Duration::from_millis(100));
+                let mut bts = bytes::BytesMut::from(&first[..]);
+                while let Some(v) = lkv.front() {
+                    if bts.len() + v.len() > BATCH_MAX {
+                        break;
+                    }
+                    bts.extend_from_slice(v);
+                    lkv.pop();
+                    n += 1;
+                }
+                bts.freeze()
+            }
+        };
+        self.inner.wkr_space.notify_all();
+        Some((rt, n))
+    }
+    fn sync_due(&self) -> bool {
+        let unsynced = self.inner.unsynced.load(Ordering::SeqCst);
+        match self.config().fsync {
+            Fsync::Never => false,
+            Fsync::Bytes(n) => unsynced >= n,
+            Fsync::Interval(d) => {
+                unsynced > 0
+                    && match self.inner.synctm.lock() {
+                        Ok(v) => v.elapsed() >= d,
+                        Err(e) => e.into_inner().elapsed() >= d,
+                    }
             }
         }
-        if let Ok(mut lkv) = self.inner.flfd.lock() {
-            *lkv = None;
-            self.inner.flln.store(0, Ordering::SeqCst);
+    }
+    /// A `push_sync`/`flush_and_wait` chunk is written but not synced yet.
+    /// It stays requested while the chunk is still queued.
+    fn sync_requested(&self) -> bool {
+        let upto = self.inner.sync_upto.load(Ordering::SeqCst);
+        upto > self.inner.synced.load(Ordering::SeqCst)
+            && upto <= self.inner.handled.load(Ordering::SeqCst)
+    }
+    /// `sync_data` when forced, requested or due by `Config::fsync`.
+    fn syncs(&self, force: bool) {
+        // loaded first, so `synced` never claims chunks written after the sync
+        let handled = self.inner.handled.load(Ordering::SeqCst);
+        if !force && !self.sync_requested() && !self.sync_due() {
+            return;
+        }
+        let unsynced = self.inner.unsynced.load(Ordering::SeqCst);
+        if unsynced > 0 {
+            if let Ok(lkv) = self.inner.flfd.lock() {
+                if let Some(v) = &*lkv {
+                    if let Err(e) = v.sync_data() {
+                        self.report(&e);
+                    }
+                }
+            }
+        }
+        self.inner.unsynced.store(0, Ordering::SeqCst);
+        match self.inner.synctm.lock() {
+            Ok(mut v) => *v = Instant::now(),
+            Err(e) => *e.into_inner() = Instant::now(),
+        }
+        self.inner.synced.store(handled, Ordering::SeqCst);
+        self.inner.wkr_synced.notify_all();
+    }
+    /// Blocks until everything queued before the call is written and synced.
+    /// Fails once the spliter is stopped or no `start`/`run`/`run_async` writer is running.
+    pub fn flush_and_wait(&self) -> io::Result<()> {
+        let target = self.inner.pushed.load(Ordering::SeqCst);
+        while self.inner.synced.load(Ordering::SeqCst) < target {
+            if self.inner.ctx.done() {
+                return Err(crate::ioerr("FileSpliter is stopped", None));
+            }
+            if self.inner.writers.load(Ordering::SeqCst) == 0 {
+                return Err(crate::ioerr(
+                    "FileSpliter has no writer running",
+                    Some(io::ErrorKind::NotConnected),
+                ));
+            }
+            self.inner.sync_upto.fetch_max(target, Ordering::SeqCst);
+            self.notify_writer();
+            let _ = self
+                .inner
+                .wkr_synced
+                .wait_timeout(Duration::from_millis(10));
         }
         Ok(())
     }
-    fn appends(&self, bts: &[u8]) {
-        let ctxs =
-            crate::Context::with_timeout(Some(self.inner.ctx.clone()), Duration::from_secs(5));
+    fn appends(&self, ctx: &crate::Context, bts: &[u8]) {
+        let ctxs = crate::Context::with_timeout(Some(ctx.clone()), Duration::from_secs(5));
         let mut flfd = None;
         while !ctxs.done() {
             if let Ok(v) = self.inner.flfd.lock() {
@@ -120,51 +538,80 @@ impl FileSpliter {
                     if e.kind() == std::io::ErrorKind::Interrupted {
                         continue;
                     }
-                    println!("filespliter.appends.checkfl err:{}", e);
+                    self.report(&e);
                     return;
                 } else {
                     break;
                 }
             }
             if let Some(fle) = &mut *lkv {
-                crate::write_all(&self.inner.ctx, fle, bts);
-                self.inner.flln.fetch_add(bts.len(), Ordering::SeqCst);
+                match crate::write_all(ctx, fle, bts) {
+                    Ok(_) => {
+                        self.inner.flln.fetch_add(bts.len(), Ordering::SeqCst);
+                        self.inner
+                            .unsynced
+                            .fetch_add(bts.len() as u64, Ordering::SeqCst);
+                    }
+                    Err(e) => {
+                        self.report(&e);
+                        // reopened by the next write
+                        *lkv = None;
+                    }
+                }
             }
         }
     }
     fn checkfl(&self, lkv: &mut MutexGuard<Option<File>>) -> io::Result<()> {
+        let cfg = self.config();
         match &mut **lkv {
             Some(v) => {
-                let max = self.inner.cfg.flsize as usize * 1024;
-                if self.inner.flln.load(Ordering::SeqCst) > max {
+                let max = cfg.flsize as usize * 1024;
+                let full = cfg.flsize > 0 && self.inner.flln.load(Ordering::SeqCst) > max;
+                if full || self.period_end(&cfg) {
                     // self.copyfl(v);
                     // v.seek(std::io::SeekFrom::Start(0))?;
-                    let _ = v;
+                    if cfg.fsync != Fsync::Never && self.inner.unsynced.load(Ordering::SeqCst) > 0 {
+                        if let Err(e) = v.sync_data() {
+                            self.report(&e);
+                        }
+                        self.inner.unsynced.store(0, Ordering::SeqCst);
+                    }
                     **lkv = None;
                     std::thread::sleep(Duration::from_millis(10));
-                    self.movefls();
+                    self.rotate(&cfg);
                     return Err(crate::ioerr(
                         "need open",
                         Some(std::io::ErrorKind::Interrupted),
                     ));
-                    /* let v = File::create(self.inner.cfg.flpath.as_str())?;
+                    /* let v = File::create(cfg.flpath.as_str())?;
                     **lkv = Some(v);
                     self.inner.flln.store(0, Ordering::SeqCst); */
                 }
             }
             None => {
-                let pth = self.inner.cfg.flpath.as_str();
-                let pths = Path::new(self.inner.cfg.flpath.as_str());
+                let pth = cfg.flpath.as_str();
+                let pths = Path::new(cfg.flpath.as_str());
                 if let Some(o) = pths.parent() {
                     if o.exists() && !o.is_dir() {
                         return Err(crate::ioerr("file path is err", None));
                     }
                     std::fs::create_dir_all(o);
                 }
-                let sz = match std::fs::metadata(pth) {
+                let mut sz = match std::fs::metadata(pth) {
                     Ok(v) => v.len(),
                     Err(_e) => 0,
                 };
+                if cfg.period != Period::Never {
+                    // a file left from an earlier period is rotated before appending
+                    if let Ok(tm) = std::fs::metadata(pth).and_then(|v| v.modified()) {
+                        if cfg.period_key(tm) != cfg.period_key(SystemTime::now()) {
+                            self.set_fltm(Some(tm));
+                            self.rotate(&cfg);
+                            sz = 0;
+                        }
+                    }
+                    self.set_fltm(Some(SystemTime::now()));
+                }
                 let v = std::fs::OpenOptions::new()
                     .read(true)
                     .append(true)
@@ -177,14 +624,15 @@ impl FileSpliter {
         Ok(())
     }
     fn copyfl(&self, fle: &mut File) {
-        if self.inner.cfg.flcount <= 1 {
+        let cfg = self.config();
+        if cfg.flcount <= 1 {
             return;
         }
         if let Err(e) = fle.seek(std::io::SeekFrom::Start(0)) {
             return;
         }
-        self.movefls();
-        let mut flne = match File::create(format!("{}.{}", &self.inner.cfg.flpath, 1)) {
+        self.movefls(&cfg, &cfg.flpath);
+        let mut flne = match File::create(format!("{}.{}", &cfg.flpath, 1)) {
             Ok(v) => v,
             Err(_) => return,
         };
@@ -202,38 +650,285 @@ impl FileSpliter {
         }
     }
 
-    fn movefls(&self) {
-        if self.inner.cfg.flcount <= 1 {
-            std::fs::remove_file(&self.inner.cfg.flpath);
+    fn set_fltm(&self, tm: Option<SystemTime>) {
+        match self.inner.fltm.lock() {
+            Ok(mut v) => *v = tm,
+            Err(e) => *e.into_inner() = tm,
+        }
+    }
+    fn period_end(&self, cfg: &Config) -> bool {
+        if cfg.period == Period::Never {
+            return false;
+        }
+        let tm = match self.inner.fltm.lock() {
+            Ok(v) => *v,
+            Err(e) => *e.into_inner(),
+        };
+        match tm {
+            Some(v) => cfg.period_key(v) != cfg.period_key(SystemTime::now()),
+            None => false,
+        }
+    }
+    /// With `gzip` the numbered files are shifted and compressed by the gzip
+    /// thread, so the writer only renames `flpath` and goes on with a new file.
+    fn rotate(&self, cfg: &Config) {
+        let job = if cfg.period == Period::Never {
+            if cfg.gzip && cfg.flcount > 1 {
+                let seq = self.inner.gzseq.fetch_add(1, Ordering::SeqCst);
+                let pth = format!("{}.rotating{}", &cfg.flpath, seq);
+                std::fs::rename(&cfg.flpath, &pth).ok().map(|_| (pth, true))
+            } else {
+                self.movefls(cfg, &cfg.flpath);
+                None
+            }
+        } else {
+            let tm = match self.inner.fltm.lock() {
+                Ok(v) => *v,
+                Err(e) => *e.into_inner(),
+            };
+            let name = format!(
+                "{}.{}",
+                &cfg.flpath,
+                crate::strftime(tm.unwrap_or_else(SystemTime::now), &cfg.tmfmt)
+            );
+            let mut pth = name.clone();
+            let mut i = 1;
+            while Path::new(&pth).exists() || Path::new(&format!("{}.gz", &pth)).exists() {
+                pth = format!("{}.{}", &name, i);
+                i += 1;
+            }
+            let rotated = std::fs::rename(&cfg.flpath, &pth).ok();
+            match rotated {
+                Some(_) if cfg.gzip => Some((pth, false)),
+                _ => None,
+            }
+        };
+        match job {
+            Some((pth, shift)) => self.push_gzip(GzJob {
+                cfg: Arc::new(cfg.clone()),
+                pth,
+                shift,
+            }),
+            // else the gzip thread cleans up after the job
+            None => self.cleanup(cfg),
+        }
+    }
+    /// Finishes rotations a crashed process left staged as `flpath.rotatingN`
+    /// and drops half written `.gz.tmp` files, whose source is still there.
+    fn recover(&self) {
+        let cfg = self.config();
+        let mut staged = Vec::new();
+        for (suffix, pth) in siblings(&cfg) {
+            if let Some(n) = suffix
+                .strip_prefix("rotating")
+                .and_then(|v| v.parse::<u64>().ok())
+            {
+                staged.push((n, pth));
+            } else if suffix.ends_with(".gz.tmp") {
+                let _ = std::fs::remove_file(&pth);
+            }
+        }
+        // oldest first, so the last staged one ends up as `flpath.1`
+        staged.sort_by_key(|v| v.0);
+        if let Some((n, _)) = staged.last() {
+            // new staged names must not overwrite the ones still queued
+            self.inner.gzseq.fetch_max(n + 1, Ordering::SeqCst);
+        }
+        for (_, pth) in staged {
+            let pth = pth.to_string_lossy().to_string();
+            if cfg.gzip {
+                self.push_gzip(GzJob {
+                    cfg: cfg.clone(),
+                    pth,
+                    shift: true,
+                });
+            } else {
+                self.movefls(&cfg, &pth);
+            }
+        }
+    }
+    fn push_gzip(&self, job: GzJob) {
+        let mut lkv = match self.inner.gzq.lock() {
+            Ok(v) => v,
+            Err(e) => e.into_inner(),
+        };
+        lkv.jobs.push_back(job);
+        if lkv.running {
+            return;
+        }
+        lkv.running = true;
+        let flspt = self.clone();
+        lkv.thr = Some(std::thread::spawn(move || flspt.run_gzip()));
+    }
+    fn run_gzip(&self) {
+        loop {
+            let job = {
+                let mut lkv = match self.inner.gzq.lock() {
+                    Ok(v) => v,
+                    Err(e) => e.into_inner(),
+                };
+                match lkv.jobs.pop_front() {
+                    Some(v) => v,
+                    None => {
+                        lkv.running = false;
+                        return;
+                    }
+                }
+            };
+            let pth = if job.shift {
+                let pth = self.movefls(&job.cfg, &job.pth);
+                if pth.is_none() {
+                    let _ = std::fs::remove_file(&job.pth);
+                }
+                pth
+            } else {
+                Some(job.pth)
+            };
+            if let Some(pth) = pth {
+                if let Err(e) = gzip_file(&pth) {
+                    self.report(&crate::ioerr(
+                        format!("gzip {} err:{}", &pth, e),
+                        Some(e.kind()),
+                    ));
+                }
+            }
+            self.cleanup(&job.cfg);
+        }
+    }
+    /// Waits until the queued compressions are done.
+    fn wait_gzip(&self) {
+        loop {
+            let thr = match self.inner.gzq.lock() {
+                Ok(mut v) => v.thr.take(),
+                Err(e) => e.into_inner().thr.take(),
+            };
+            match thr {
+                Some(v) => {
+                    let _ = v.join();
+                }
+                None => break,
+            }
+        }
+    }
+    /// Retention of rotated files by age, and by count for time based rotation
+    /// (`movefls` keeps the count of numbered ones). Other files next to
+    /// `flpath` are left alone, see `is_rotated`.
+    fn cleanup(&self, cfg: &Config) {
+        let keepn = cfg.period != Period::Never && cfg.flcount > 0;
+        if !keepn && cfg.maxage.is_zero() {
             return;
         }
-        let mut i = self.inner.cfg.flcount - 1;
-        std::fs::remove_file(format!("{}.{}", &self.inner.cfg.flpath, i));
+        let mut fls = Vec::new();
+        for (suffix, pth) in siblings(cfg) {
+            if !is_rotated(cfg, &suffix) {
+                continue;
+            }
+            if let Ok(tm) = std::fs::metadata(&pth).and_then(|v| v.modified()) {
+                fls.push((pth, tm));
+            }
+        }
+        fls.sort_by_key(|v| std::cmp::Reverse(v.1));
+        let now = SystemTime::now();
+        for (i, (pth, tm)) in fls.iter().enumerate() {
+            let old = !cfg.maxage.is_zero()
+                && now.duration_since(*tm).unwrap_or(Duration::ZERO) > cfg.maxage;
+            if old || (keepn && i + 1 >= cfg.flcount as usize) {
+                let _ = std::fs::remove_file(pth);
+            }
+        }
+    }
+
+    /// Shifts `flpath.N` and `flpath.N.gz` up by one and renames `src` to
+    /// `flpath.1`, returns it.
+    fn movefls(&self, cfg: &Config, src: &str) -> Option<String> {
+        // most slots don't exist yet, or hold only one of `.N` and `.N.gz`
+        let check = |rst: io::Result<()>, pth: &str| {
+            if let Err(e) = rst {
+                if e.kind() != io::ErrorKind::NotFound {
+                    self.report(&crate::ioerr(
+                        format!("rotate {} err:{}", pth, e),
+                        Some(e.kind()),
+                    ));
+                }
+            }
+        };
+        if cfg.flcount <= 1 {
+            check(std::fs::remove_file(src), src);
+            return None;
+        }
+        let mut i = cfg.flcount - 1;
+        for pth in [
+            format!("{}.{}", &cfg.flpath, i),
+            format!("{}.{}.gz", &cfg.flpath, i),
+        ] {
+            check(std::fs::remove_file(&pth), &pth);
+        }
         while i > 1 {
-            std::fs::rename(
-                format!("{}.{}", &self.inner.cfg.flpath, i - 1),
-                format!("{}.{}", &self.inner.cfg.flpath, i),
-            );
+            for ext in ["", ".gz"] {
+                let pth = format!("{}.{}{}", &cfg.flpath, i - 1, ext);
+                let rst = std::fs::rename(&pth, format!("{}.{}{}", &cfg.flpath, i, ext));
+                check(rst, &pth);
+            }
             i -= 1;
         }
-        std::fs::rename(
-            &self.inner.cfg.flpath,
-            format!("{}.{}", &self.inner.cfg.flpath, 1),
-        );
+        let pth = format!("{}.{}", &cfg.flpath, 1);
+        std::fs::rename(src, &pth).ok().map(|_| pth)
     }
 
     pub fn push(&self, bts: &[u8]) {
         self.pushbox(bts.to_vec());
     }
     pub fn pushbox<T: Into<bytes::Bytes>>(&self, bts: T) {
-        if let Ok(mut lkv) = self.inner.buf.lock() {
-            lkv.push(bts.into());
-            self.inner.wkr.notify_all();
+        self.pushes(bts.into());
+    }
+    /// Queues `bts` by the overflow policy, returns `pushed` once it is queued or dropped.
+    fn pushes(&self, bts: bytes::Bytes) -> u64 {
+        let overflow = self.config().overflow;
+        loop {
+            let mut lkv = match self.inner.buf.lock() {
+                Ok(v) => v,
+                Err(e) => e.into_inner(),
+            };
+            if lkv.len() >= lkv.max() {
+                match overflow {
+                    Overflow::DropOldest => {
+                        // ListDequeMax drops the front itself
+                        self.inner.dropped.fetch_add(1, Ordering::Relaxed);
+                        self.inner.handled.fetch_add(1, Ordering::SeqCst);
+                    }
+                    Overflow::DropNewest => {
+                        self.inner.dropped.fetch_add(1, Ordering::Relaxed);
+                        return self.inner.pushed.load(Ordering::SeqCst);
+                    }
+                    Overflow::Block => {
+                        drop(lkv);
+                        if self.inner.ctx.done() {
+                            self.inner.dropped.fetch_add(1, Ordering::Relaxed);
+                            return self.inner.pushed.load(Ordering::SeqCst);
+                        }
+                        let _ = self.inner.wkr_space.wait_timeout(Duration::from_millis(10));
+                        continue;
+                    }
+                }
+            }
+            lkv.push(bts);
+            let seq = self.inner.pushed.fetch_add(1, Ordering::SeqCst) + 1;
+            drop(lkv);
+            self.notify_writer();
+            return seq;
         }
     }
+    /// Like `push`, and syncs the file once it is written.
+    pub fn push_sync(&self, bts: &[u8]) {
+        let seq = self.pushes(bytes::Bytes::copy_from_slice(bts));
+        self.inner.sync_upto.fetch_max(seq, Ordering::SeqCst);
+        self.notify_writer();
+    }
     pub fn pushs(&self, conts: &str) {
         self.push(conts.as_bytes());
     }
+    /// Flushes the open file only, `File` keeps no buffer so chunks still queued
+    /// are not written. Use `flush_and_wait` to wait for them.
     pub fn flush(&self) {
         if let Ok(mut lkv) = self.inner.flfd.lock() {
             if let Some(v) = &mut *lkv {
@@ -242,3 +937,114 @@ impl FileSpliter {
         }
     }
 }
+
+/// Files next to `flpath` named `flpath.<suffix>`, as `(suffix, path)`.
+fn siblings(cfg: &Config) -> Vec<(String, std::path::PathBuf)> {
+    let pths = Path::new(&cfg.flpath);
+    let prefix = match pths.file_name() {
+        Some(v) => format!("{}.", v.to_string_lossy()),
+        None => return Vec::new(),
+    };
+    let dir = match pths.parent() {
+        Some(v) if !v.as_os_str().is_empty() => v,
+        _ => Path::new("."),
+    };
+    let mut rts = Vec::new();
+    if let Ok(rd) = std::fs::read_dir(dir) {
+        for ent in rd.flatten() {
+            let name = ent.file_name().to_string_lossy().to_string();
+            if let Some(v) = name.strip_prefix(&prefix) {
+                rts.push((v.to_string(), ent.path()));
+            }
+        }
+    }
+    rts
+}
+
+/// Whether `suffix`, the file name after `flpath.`, is one `rotate` produces:
+/// `N`, `<tmfmt>` or `<tmfmt>.N`, each optionally followed by `.gz`, or a
+/// staged `rotatingN`.
+fn is_rotated(cfg: &Config, suffix: &str) -> bool {
+    let is_num = |v: &str| !v.is_empty() && v.bytes().all(|c| c.is_ascii_digit());
+    let is_tm = |v: &str| {
+        let mut parsed = chrono::format::Parsed::new();
+        !cfg.tmfmt.is_empty()
+            && chrono::format::parse(
+                &mut parsed,
+                v,
+                chrono::format::StrftimeItems::new(&cfg.tmfmt),
+            )
+            .is_ok()
+    };
+    if matches!(suffix.strip_prefix("rotating"), Some(v) if is_num(v)) {
+        return true;
+    }
+    let s = suffix.strip_suffix(".gz").unwrap_or(suffix);
+    if is_num(s) || is_tm(s) {
+        return true;
+    }
+    match s.rsplit_once('.') {
+        Some((tm, n)) => is_num(n) && is_tm(tm),
+        None => false,
+    }
+}
+
+/// `pth` -> `pth.gz` keeping the modified time for age retention.
+fn gzip_file(pth: &str) -> io::Result<()> {
+    let gzp = format!("{}.gz", pth);
+    let tmp = format!("{}.tmp", &gzp);
+    let mut src = File::open(pth)?;
+    let tm = src.metadata()?.modified()?;
+    let mut enc =
+        flate2::write::GzEncoder::new(File::create(&tmp)?, flate2::Compression::default());
+    if let Err(e) = io::copy(&mut src, &mut enc) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    let fl = enc.finish()?;
+    fl.sync_all()?;
+    fl.set_modified(tm)?;
+    std::fs::rename(&tmp, &gzp)?;
+    std::fs::remove_file(pth)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::{Config, FileSpliter};
+
+    // one pass of the writer loop, without the sync
+    fn write_pass(flspt: &FileSpliter) {
+        let ctx = crate::Context::background(None);
+        while let Some((v, n)) = flspt.pops() {
+            flspt.appends(&ctx, &v[..]);
+            flspt.inner.handled.fetch_add(n, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn push_sync_after_pass() {
+        let dir = std::env::temp_dir().join(format!("ruisutil-pushsync-{}", std::process::id()));
+        let flspt = FileSpliter::new(
+            &crate::Context::background(None),
+            Config {
+                flpath: dir.join("a.log").to_string_lossy().to_string(),
+                ..Default::default()
+            },
+        );
+        flspt.pushs("a\n");
+        write_pass(&flspt);
+        // lands between the write pass and its sync
+        flspt.push_sync(b"b\n");
+        flspt.syncs(false);
+        assert_eq!(flspt.inner.synced.load(Ordering::SeqCst), 0);
+        write_pass(&flspt);
+        flspt.syncs(false);
+        assert!(
+            flspt.inner.synced.load(Ordering::SeqCst) >= flspt.inner.pushed.load(Ordering::SeqCst)
+        );
+        flspt.shutdown();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -6,7 +6,7 @@ use std::{
     time::{self, Duration},
 };
 
-pub use contianer::ArcMut;
+pub use contianer::{ArcHandle, ArcMut, ArcSwap, WeakMut};
 pub use list::ListDequeMax;
 pub use timer::Timer;
 pub use utils::*;
@@ -80,7 +80,7 @@ impl Context {
     }
     pub fn done_err(&self) -> std::io::Result<()> {
         if self.done() {
-            Err(ioerr("ctx end", Some(std::io::ErrorKind::Interrupted)))
+            Err(errs::Error::with_kind(errs::ErrorKind::ContextCanceled, "ctx end").into())
         } else {
             Ok(())
         }
@@ -226,29 +226,28 @@ mod tests {
     }
     #[derive(Clone)]
     struct Ruis {
-        inner: ArcMut<Inner>,
+        inner: ArcMut<std::sync::Mutex<Inner>>,
     }
     impl Ruis {
         pub fn new() -> Self {
             Self {
-                inner: ArcMut::new(Inner { i: 1 }),
+                inner: ArcMut::new_mutex(Inner { i: 1 }),
             }
         }
 
         pub fn set(&self, i: i32) {
-            let ins = unsafe { self.inner.muts() };
-            ins.i = i;
+            self.inner.with_lock(|v| v.i = i);
         }
         pub fn get(&self) -> i32 {
-            self.inner.i
+            self.inner.with_lock(|v| v.i)
         }
 
-        pub unsafe fn from_raw(p: *const Inner) -> std::io::Result<Self> {
+        pub unsafe fn from_raw(p: *const std::sync::Mutex<Inner>) -> std::io::Result<Self> {
             let inr = ArcMut::from_raw(p)?;
             Ok(Self { inner: inr })
         }
 
-        pub unsafe fn from_raws(p: *const Inner) -> std::io::Result<Self> {
+        pub unsafe fn from_raws(p: *const std::sync::Mutex<Inner>) -> std::io::Result<Self> {
             let inr = ArcMut::from_raws(p)?;
             Ok(Self { inner: inr })
         }
@@ -264,6 +263,7 @@ mod tests {
         let ruis1 = ruis.clone();
         ruis1.set(4);
         println!("ruis i-3:{}", ruis.get());
+        assert_eq!(ruis.get(), 4);
 
         println!("ruis incount1={}", ruis.inner.arc_count());
         std::mem::drop(ruis1);
@@ -291,6 +291,110 @@ mod tests {
         println!("ruis incountEnd={}", ruis.inner.arc_count());
     }
 
+    #[test]
+    fn arcmut_weak_ffi() {
+        use crate::{ArcHandle, WeakMut};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Counted(i32);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let am = ArcMut::new(Counted(7));
+        let wk = am.downgrade();
+        assert_eq!(am.weak_count(), 1);
+        let am2 = wk.upgrade().unwrap();
+        assert!(ArcMut::ptr_eq(&am, &am2));
+        assert!(WeakMut::ptr_eq(&wk, &am2.downgrade()));
+        assert!(!ArcMut::ptr_eq(&am, &ArcMut::new(Counted(0))));
+        assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+        assert_eq!(am.as_ptr() as usize as u64, am.ptr());
+
+        // handle round trips keep the count balanced
+        let h: ArcHandle = am2.into_handle();
+        assert_eq!(am.arc_count(), 2);
+        for _ in 0..3 {
+            let bw = unsafe { ArcMut::<Counted>::borrow_handle(h).unwrap() };
+            assert_eq!(bw.0, 7);
+            assert_eq!(am.arc_count(), 3);
+        }
+        assert_eq!(am.arc_count(), 2);
+        let back = unsafe { ArcMut::<Counted>::from_handle(h).unwrap() };
+        drop(back);
+        assert_eq!(am.arc_count(), 1);
+        assert!(unsafe { ArcMut::<Counted>::from_handle(std::ptr::null_mut()) }.is_err());
+
+        assert_eq!(wk.arc_count(), 1);
+        drop(am);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 2);
+        assert!(wk.upgrade().is_none());
+        assert_eq!(wk.arc_count(), 0);
+    }
+
+    #[test]
+    fn arcmut_locks() {
+        use crate::ArcSwap;
+        use std::sync::{Mutex, RwLock};
+
+        let cnt = ArcMut::new_mutex(0);
+        let thrs: Vec<_> = (0..4)
+            .map(|_| {
+                let cnt = cnt.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        cnt.with_lock(|v| *v += 1);
+                    }
+                })
+            })
+            .collect();
+        for thr in thrs {
+            thr.join().unwrap();
+        }
+        assert_eq!(*cnt.lock_guard(), 4000);
+
+        let rw: ArcMut<RwLock<Vec<i32>>> = ArcMut::new_rw(vec![1]);
+        rw.write_guard().push(2);
+        assert_eq!(rw.with_lock(|v| v.len()), 2);
+        {
+            let (a, b) = (rw.read_guard(), rw.read_guard());
+            assert_eq!(a[1], b[1]);
+        }
+        // a panic while holding the lock does not make it unusable
+        let rw1 = rw.clone();
+        let _ = std::thread::spawn(move || {
+            let _lk = rw1.write_guard();
+            panic!("poison");
+        })
+        .join();
+        assert_eq!(*rw.read_guard(), vec![1, 2]);
+        // the std methods stay reachable through Deref
+        assert!(rw.read().is_err());
+        let mx: ArcMut<Mutex<i32>> = ArcMut::new_mutex(1);
+        assert_eq!(mx.with_lock(|v| *v), 1);
+
+        let cfg = ArcMut::new_swap(String::from("v1"));
+        let snap = cfg.load();
+        cfg.store(String::from("v2"));
+        assert_eq!(*snap, "v1");
+        assert_eq!(*cfg.load(), "v2");
+        assert_eq!(*cfg.swap(String::from("v3")), "v2");
+        let sw = ArcSwap::new(0u64);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        sw.update(|v| v + 1);
+                    }
+                });
+            }
+        });
+        assert_eq!(*sw.load(), 400);
+    }
+
     #[test]
     fn rands() {
         println!("randtms:{}", crate::randtms());
@@ -488,6 +592,510 @@ mod tests {
         println!("-----------------tos end");
     }
 
+    #[test]
+    fn errs_context() {
+        use crate::errs::{Error, ErrorKind, ResultExt};
+        use std::error::Error as _;
+        use std::io;
+
+        let rst: io::Result<()> = Err(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        let e = rst
+            .context("opening log file")
+            .with_context(|| format!("starting {}", "logger"))
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "starting logger: opening log file: no such file"
+        );
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        assert_eq!(e.error_kind(), ErrorKind::Io);
+        assert_eq!(
+            e.downcast_ref::<io::Error>().unwrap().kind(),
+            io::ErrorKind::NotFound
+        );
+        // transparent, the io::Error has no cause
+        assert!(e.source().is_none());
+
+        let e = Error::with_kind(ErrorKind::Protocol, "bad header")
+            .with_source(io::Error::new(io::ErrorKind::UnexpectedEof, "eof"));
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(e.source().unwrap().to_string(), "eof");
+        // through io::Error and back
+        let ioe: io::Error = e.context("reading msg").into();
+        assert_eq!(ioe.to_string(), "reading msg: bad header");
+        let e = Error::from(ioe);
+        assert_eq!(e.error_kind(), ErrorKind::Protocol);
+        assert_eq!(e.to_string(), "reading msg: bad header");
+
+        let ctx = Context::background(None);
+        ctx.stop();
+        let e = Error::from(ctx.done_err().unwrap_err());
+        assert_eq!(e.error_kind(), ErrorKind::ContextCanceled);
+        assert_eq!(e.kind(), io::ErrorKind::Interrupted);
+        assert!(e.backtrace().is_none());
+        assert!(Error::news("x").with_backtrace().backtrace().is_some());
+    }
+
+    #[test]
+    fn errs_wire() {
+        use crate::errs::{Error, ErrorKind};
+        use std::io;
+
+        let e = Error::with_kind(ErrorKind::Limit, "body too large")
+            .with_code(413)
+            .with_detail("max", "1024")
+            .with_detail("got", "4096")
+            .context("upload");
+        let bts = e.encode();
+        let back = Error::decode(&bts).unwrap();
+        assert_eq!(back.to_string(), "upload: body too large");
+        assert_eq!(back.error_kind(), ErrorKind::Limit);
+        assert_eq!(back.code(), 413);
+        assert_eq!(back.detail("max"), Some("1024"));
+        assert_eq!(back.details().len(), 2);
+        assert_eq!(back.encode(), bts);
+
+        let e = Error::from(io::Error::new(io::ErrorKind::NotFound, "gone"));
+        let back = Error::decode(&e.encode()).unwrap();
+        assert_eq!(back.kind(), io::ErrorKind::NotFound);
+        assert_eq!(back.error_kind(), ErrorKind::Io);
+        assert_eq!(back.code(), 0);
+        assert_eq!(back.to_string(), "gone");
+        for k in [io::ErrorKind::Unsupported, io::ErrorKind::OutOfMemory] {
+            let back = Error::decode(&Error::new("x", k).encode()).unwrap();
+            assert_eq!(back.kind(), k);
+        }
+        // ENOSPC and EINPROGRESS have kinds without a tag, their names are kept
+        #[cfg(target_os = "linux")]
+        for k in [
+            io::Error::from_raw_os_error(28).kind(),
+            io::Error::from_raw_os_error(115).kind(),
+        ] {
+            let back = Error::decode(&Error::new("x", k).encode()).unwrap();
+            assert_eq!(back.kind(), io::ErrorKind::Other);
+            assert_eq!(
+                back.detail(crate::errs::DETAIL_IO_KIND),
+                Some(k.to_string().as_str())
+            );
+        }
+
+        for n in 0..bts.len() {
+            let e = Error::from(Error::decode(&bts[..n]).unwrap_err());
+            assert_eq!(e.error_kind(), ErrorKind::Protocol);
+        }
+        let mut bad = bts.clone();
+        bad.push(0);
+        assert!(Error::decode(&bad).is_err());
+    }
+
+    #[test]
+    fn kvcfg_parse() {
+        let dir = std::env::temp_dir().join(format!("ruisutil-kvcfg-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(
+            dir.join("main.conf"),
+            "# comment\n\
+             ; also comment\n\
+             name = ruis # trailing\n\
+             color=#fff\n\
+             quoted = \"a\\tb \\\"c\\\" # kept\"  # gone\n\
+             raw = 'x\\ny'\n\
+             long = one \\\n     two\n\
+             [db]\n\
+             host = 127.0.0.1\n\
+             include sub/more.conf\n\
+             [ ]\n\
+             top = 1\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("sub/more.conf"), "[db]\nport = 3306\nextra=yes\n").unwrap();
+        let cfgs = KVConfig::from_file(dir.join("main.conf")).unwrap();
+        assert_eq!(cfgs.get("name").unwrap(), "ruis");
+        assert_eq!(cfgs.get("color").unwrap(), "#fff");
+        assert_eq!(cfgs.get("quoted").unwrap(), "a\tb \"c\" # kept");
+        assert_eq!(cfgs.get("raw").unwrap(), "x\\ny");
+        assert_eq!(cfgs.get("long").unwrap(), "one two");
+        assert_eq!(cfgs.get("db.host").unwrap(), "127.0.0.1");
+        assert_eq!(cfgs.geti("db.port"), Some(3306));
+        assert_eq!(cfgs.get("db.extra").unwrap(), "yes");
+        assert_eq!(cfgs.geti("top"), Some(1));
+        assert_eq!(cfgs.iter().count(), 9);
+
+        let back = KVConfig::parse(cfgs.to_string().as_bytes()).unwrap();
+        assert_eq!(back.get("quoted"), cfgs.get("quoted"));
+
+        std::fs::write(dir.join("bad.conf"), "a=1\n\nb = \"open\n").unwrap();
+        let e = KVConfig::from_file(dir.join("bad.conf")).err().unwrap();
+        assert!(e.to_string().contains("bad.conf:3:"), "{}", e);
+        assert!(KVConfig::parse(b"novalue").is_err());
+        assert!(KVConfig::from_file(dir.join("none.conf")).is_err());
+        std::fs::write(dir.join("loop.conf"), "include loop.conf\n").unwrap();
+        assert!(KVConfig::from_file(dir.join("loop.conf")).is_err());
+        assert_eq!(KVConfig::from_bytes(b"novalue\nk=v").iter().count(), 1);
+        // split at the first '=', the old parser took the last one
+        let cfgs = KVConfig::parse(b"a=b=c\nurl = http://h/?x=1&y=2").unwrap();
+        assert_eq!(cfgs.get("a").unwrap(), "b=c");
+        assert!(cfgs.get("a=b").is_none());
+        assert_eq!(cfgs.get("url").unwrap(), "http://h/?x=1&y=2");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn kvcfg_typed() {
+        let cfgs = KVConfig::from_bytes(
+            b"on = Yes\n\
+              off = 0\n\
+              bad = maybe\n\
+              pi = 3.14\n\
+              tmout = 1h30m\n\
+              tick = 250ms\n\
+              mem = 1.5GB\n\
+              buf = 4k\n\
+              hosts = a, b,,c ",
+        );
+        assert_eq!(cfgs.get_bool("on"), Some(true));
+        assert_eq!(cfgs.get_bool("off"), Some(false));
+        assert_eq!(cfgs.get_bool("bad"), None);
+        assert_eq!(cfgs.get_bool("none"), None);
+        assert_eq!(cfgs.get_f64("pi"), Some(3.14));
+        assert_eq!(cfgs.get_duration("tmout"), Some(Duration::from_secs(5400)));
+        assert_eq!(cfgs.get_duration("tick"), Some(Duration::from_millis(250)));
+        assert_eq!(cfgs.get_duration("bad"), None);
+        assert_eq!(cfgs.get_size("mem"), Some(1536 * 1024 * 1024));
+        assert_eq!(cfgs.get_size("buf"), Some(4096));
+        assert_eq!(cfgs.get_list("hosts").unwrap(), vec!["a", "b", "c"]);
+
+        assert_eq!(crate::parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert!(crate::parse_duration("-1").is_err());
+        assert!(crate::parse_duration("5 parsecs").is_err());
+        let sz = 10 * 1024 * 1024;
+        assert_eq!(
+            crate::bytes::parse_human_readable(&crate::bytes::conv_human_readable(sz)).unwrap(),
+            sz
+        );
+    }
+
+    #[test]
+    fn kvcfg_layered() {
+        use crate::conf::{LayeredConfig, Source};
+        let dir = std::env::temp_dir().join(format!("ruisutil-kvlay-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pth = dir.join("app.conf");
+        std::fs::write(&pth, "name=file\n[db]\nhost=filehost\nmax_conn=10\nport=1\n").unwrap();
+        std::env::set_var("RUISLAY_DB_HOST", "envhost");
+        std::env::set_var("RUISLAY_DB_MAX_CONN", "20");
+        std::env::set_var("RUISLAY_NEW__KEY", "x");
+        std::env::set_var("RUISLAYX_NAME", "other");
+
+        let mut cfg = LayeredConfig::new();
+        cfg.set_default("name", "def")
+            .set_default("level", "info")
+            .file(&pth)
+            .unwrap()
+            .file_opt(dir.join("none.conf"))
+            .unwrap()
+            .env("RUISLAY")
+            .args(vec!["prog", "--db.port=2", "--verbose", "--", "--name=no"])
+            .unwrap();
+        assert_eq!(cfg.get("name").unwrap(), "file");
+        assert_eq!(cfg.source_of("name"), Some(&Source::File(pth.clone())));
+        assert_eq!(cfg.get("level").unwrap(), "info");
+        assert_eq!(cfg.source_of("level"), Some(&Source::Default));
+        assert_eq!(cfg.get("db.host").unwrap(), "envhost");
+        assert_eq!(
+            cfg.source_of("db.host"),
+            Some(&Source::Env("RUISLAY_DB_HOST".into()))
+        );
+        assert_eq!(cfg.geti("db.max_conn"), Some(20));
+        assert_eq!(cfg.get("new_key").unwrap(), "x");
+        assert_eq!(cfg.geti("db.port"), Some(2));
+        assert_eq!(cfg.source_of("db.port"), Some(&Source::Arg));
+        assert_eq!(cfg.get_bool("verbose"), Some(true));
+        assert_eq!(cfg.source_of("none"), None);
+
+        assert!(cfg.file(dir.join("none.conf")).is_err());
+        // only the file itself may be missing
+        std::fs::write(dir.join("inc.conf"), "include none.conf\n").unwrap();
+        let e = cfg.file_opt(dir.join("inc.conf")).err().unwrap();
+        assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+        assert!(LayeredConfig::new().args(vec!["--=1"]).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn kvcfg_document() {
+        use crate::conf::KVDocument;
+        let conts = "# app config\n\
+                     name = ruis   # the name\n\
+                     \n\
+                     quoted = \"a b\" ; keep\n\
+                     long = one \\\n  two\n\
+                     include extra.conf\n\
+                     \n\
+                     # database\n\
+                     [db]\n\
+                     host = localhost\n\
+                     port=1\n\
+                     \n\
+                     [log]\n\
+                     level = info\n";
+        let mut doc = KVDocument::parse(conts.as_bytes()).unwrap();
+        assert_eq!(doc.to_string(), conts);
+        assert_eq!(doc.get("long"), Some("one two"));
+        assert_eq!(doc.get("db.port"), Some("1"));
+        assert_eq!(
+            doc.keys(),
+            vec!["name", "quoted", "long", "db.host", "db.port", "log.level"]
+        );
+
+        doc.set("name", "new name")
+            .set("quoted", "x#y")
+            .set("long", "short")
+            .set("db.port", "3306")
+            .set("db.user", "root")
+            .set("top", "1")
+            .set("cache.size", "10")
+            .set("log.file", "a.log");
+        assert_eq!(doc.remove("log.level"), Some("info".to_string()));
+        assert_eq!(doc.remove("none"), None);
+        assert_eq!(
+            doc.to_string(),
+            "# app config\n\
+             name = new name   # the name\n\
+             \n\
+             quoted = \"x#y\" ; keep\n\
+             long = short\n\
+             top = 1\n\
+             cache.size = 10\n\
+             include extra.conf\n\
+             \n\
+             # database\n\
+             [db]\n\
+             host = localhost\n\
+             port=3306\n\
+             user = root\n\
+             \n\
+             [log]\n\
+             file = a.log\n"
+        );
+        let cfgs = KVConfig::parse(doc.to_string().replace("include extra.conf", "").as_bytes())
+            .unwrap();
+        assert_eq!(cfgs.get("quoted").unwrap(), "x#y");
+        assert_eq!(cfgs.to_string(), doc.to_config().to_string());
+        assert!(KVDocument::parse(b"a=1\nbad\n")
+            .err()
+            .unwrap()
+            .to_string()
+            .contains(":2:"));
+        // one tokenizer, the same errors
+        for bad in ["[db", "= 1", "a = \"x", "include 'x", "[a] b"] {
+            assert_eq!(
+                KVDocument::parse(bad.as_bytes()).err().unwrap().to_string(),
+                KVConfig::parse(bad.as_bytes()).err().unwrap().to_string()
+            );
+        }
+
+        let dir = std::env::temp_dir().join(format!("ruisutil-kvdoc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pth = dir.join("app.conf");
+        doc.save(&pth).unwrap();
+        let back = KVDocument::from_file(&pth).unwrap();
+        assert_eq!(back.to_string(), doc.to_string());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn kvcfg_watch() {
+        use std::sync::{Arc, Mutex};
+        // rename, so the watcher never sees a half written file
+        let replace = |pth: &std::path::Path, conts: &str| {
+            let tmp = pth.with_extension("tmp");
+            std::fs::write(&tmp, conts).unwrap();
+            std::fs::rename(&tmp, pth).unwrap();
+        };
+        let dir = std::env::temp_dir().join(format!("ruisutil-kvwatch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pth = dir.join("app.conf");
+        std::fs::write(&pth, "a=1\nb=2\n").unwrap();
+        let wtch = crate::conf::ConfigWatcher::new(&pth).unwrap();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seens = seen.clone();
+        wtch.subscribe(move |cfg, keys| {
+            seens.lock().unwrap().push((cfg.geti("a"), keys.to_vec()));
+        });
+        let errs = Arc::new(Mutex::new(0));
+        let errss = errs.clone();
+        wtch.on_error(move |_| *errss.lock().unwrap() += 1);
+        let old = wtch.config();
+        assert!(wtch.check().unwrap().is_empty());
+
+        let ctx = Context::background(None);
+        let thr = wtch.start(&ctx, Duration::from_millis(20));
+        replace(&pth, "a=10\nc=3\n");
+        let tms = SystemTime::now();
+        while seen.lock().unwrap().is_empty() && tms.elapsed().unwrap() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            seen.lock().unwrap()[0],
+            (Some(10), vec!["a".to_string(), "b".to_string(), "c".to_string()])
+        );
+        assert_eq!(old.geti("a"), Some(1));
+        assert_eq!(wtch.config().geti("a"), Some(10));
+
+        replace(&pth, "a = \"broken\n");
+        let tms = SystemTime::now();
+        while *errs.lock().unwrap() == 0 && tms.elapsed().unwrap() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(*errs.lock().unwrap(), 1);
+        assert_eq!(wtch.config().geti("a"), Some(10));
+        ctx.stop();
+        thr.join().unwrap();
+        assert_eq!(seen.lock().unwrap().len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn kvcfg_watch_subs() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+        let dir = std::env::temp_dir().join(format!("ruisutil-kvsubs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pth = dir.join("app.conf");
+        std::fs::write(&pth, "a=1\n").unwrap();
+        let wtch = crate::conf::ConfigWatcher::new(&pth).unwrap();
+        let (outer, inner) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let (wtchc, outerc, innerc) = (wtch.clone(), outer.clone(), inner.clone());
+        // subscribes from inside a callback, once
+        wtch.subscribe(move |_, _| {
+            if outerc.fetch_add(1, Ordering::SeqCst) == 0 {
+                let innerc = innerc.clone();
+                wtchc.subscribe(move |_, _| {
+                    innerc.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+        });
+        std::fs::write(&pth, "a=2\n").unwrap();
+        assert_eq!(wtch.reload().unwrap(), vec!["a".to_string()]);
+        std::fs::write(&pth, "a=3\n").unwrap();
+        assert_eq!(wtch.reload().unwrap(), vec!["a".to_string()]);
+        assert_eq!(outer.load(Ordering::SeqCst), 2);
+        assert_eq!(inner.load(Ordering::SeqCst), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(all(feature = "tomls", feature = "jsons"))]
+    #[test]
+    fn kvcfg_formats() {
+        let dir = std::env::temp_dir().join(format!("ruisutil-kvfmt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("app.toml"),
+            "name = \"ruis\"\n\
+             debug = true\n\
+             hosts = [\"a\", \"b\"]\n\
+             [db]\n\
+             port = 5432\n\
+             tmout = \"30s\"\n\
+             [[servers]]\n\
+             host = \"s1\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("app.JSON"),
+            r#"{"name":"ruis","debug":true,"hosts":["a","b"],"none":null,
+                "db":{"port":5432,"tmout":"30s"},"servers":[{"host":"s1"}]}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("app.conf"), "name=ruis\ndebug=true\nhosts=a,b\n\
+            [db]\nport=5432\ntmout=30s\n[servers.0]\nhost=s1\n").unwrap();
+        let kv = KVConfig::load(dir.join("app.conf")).unwrap();
+        for name in ["app.toml", "app.JSON"] {
+            let cfgs = KVConfig::load(dir.join(name)).unwrap();
+            assert_eq!(cfgs.to_string(), kv.to_string(), "{}", name);
+            assert_eq!(cfgs.geti("db.port"), Some(5432));
+            assert_eq!(cfgs.get_bool("debug"), Some(true));
+            assert_eq!(cfgs.get_list("hosts").unwrap(), vec!["a", "b"]);
+            assert_eq!(cfgs.get_duration("db.tmout"), Some(Duration::from_secs(30)));
+            assert_eq!(cfgs.get("servers.0.host").unwrap(), "s1");
+        }
+        assert!(KVConfig::from_json(b"[1]").is_err());
+        let e = KVConfig::from_toml(b"a = ").err().unwrap();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+        // `get_list` would split "a,b" into two items
+        // items holding a ',' are indexed, the rest of the file still loads
+        let cfgs = KVConfig::from_toml(b"[db]\nhosts = [\"a,b\", \"c\"]\nport = 1").unwrap();
+        assert_eq!(cfgs.get("db.hosts.0").unwrap(), "a,b");
+        assert_eq!(cfgs.get("db.hosts.1").unwrap(), "c");
+        assert!(cfgs.get("db.hosts").is_none());
+        assert_eq!(cfgs.geti("db.port"), Some(1));
+        let cfgs = KVConfig::from_json(br#"{"hosts":["a,b","c"]}"#).unwrap();
+        assert_eq!(cfgs.get("hosts.0").unwrap(), "a,b");
+        assert_eq!(cfgs.get("hosts.1").unwrap(), "c");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "serdes")]
+    #[test]
+    fn kvcfg_serde() {
+        #[derive(serde::Deserialize, Debug)]
+        struct Db {
+            host: String,
+            port: u16,
+            #[serde(deserialize_with = "crate::conf::de_duration")]
+            tmout: Duration,
+            user: Option<String>,
+        }
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        #[serde(rename_all = "lowercase")]
+        enum Mode {
+            Dev,
+            Prod,
+        }
+        #[derive(serde::Deserialize, Debug)]
+        struct App {
+            name: String,
+            debug: bool,
+            mode: Mode,
+            #[serde(deserialize_with = "crate::conf::de_size")]
+            maxbody: u64,
+            hosts: Vec<String>,
+            db: Db,
+        }
+        let conts = "name=ruis\ndebug=on\nmode=prod\nmaxbody=2MB\nhosts=a,b\n\
+                     [db]\nhost=localhost\nport=5432\ntmout=30s\n";
+        let app: App = KVConfig::parse(conts.as_bytes())
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(app.name, "ruis");
+        assert!(app.debug);
+        assert_eq!(app.mode, Mode::Prod);
+        assert_eq!(app.maxbody, 2 * 1024 * 1024);
+        assert_eq!(app.hosts, vec!["a", "b"]);
+        assert_eq!(app.db.host, "localhost");
+        assert_eq!(app.db.port, 5432);
+        assert_eq!(app.db.tmout, Duration::from_secs(30));
+        assert_eq!(app.db.user, None);
+
+        let bad = conts.replace("port=5432", "port=high");
+        let e = KVConfig::parse(bad.as_bytes())
+            .unwrap()
+            .deserialize::<App>()
+            .unwrap_err();
+        assert!(e.to_string().contains("config key `db.port`: invalid u16 `high`"), "{}", e);
+        let bad = conts.replace("port=5432", "");
+        let e = KVConfig::parse(bad.as_bytes())
+            .unwrap()
+            .deserialize::<App>()
+            .unwrap_err();
+        assert_eq!(e.to_string(), "config key `db`: missing field `port`");
+    }
+
     #[test]
     fn xids() {
         let xid = crate::xid_unsafe();
@@ -528,6 +1136,319 @@ mod tests {
         }
     }
 
+    #[test]
+    fn filesplit_reload() {
+        let dir = std::env::temp_dir().join(format!("ruisutil-reload-{}", std::process::id()));
+        let pth1 = dir.join("a.log");
+        let pth2 = dir.join("b.log");
+        let ctx = Context::background(None);
+        let flspt = crate::filesplit::FileSpliter::new(
+            &ctx,
+            crate::filesplit::Config {
+                flpath: pth1.to_string_lossy().to_string(),
+                maxbuf: 100,
+                ..Default::default()
+            },
+        );
+        flspt.start().unwrap();
+        assert!(flspt.start().is_err());
+        flspt.pushs("one\n");
+        std::thread::sleep(Duration::from_millis(200));
+        flspt.set_config(crate::filesplit::Config {
+            flpath: pth2.to_string_lossy().to_string(),
+            maxbuf: 100,
+            ..Default::default()
+        });
+        for i in 0..50 {
+            flspt.pushs(&format!("line{}\n", i));
+        }
+        flspt.shutdown();
+        assert_eq!(std::fs::read_to_string(&pth1).unwrap(), "one\n");
+        let conts = std::fs::read_to_string(&pth2).unwrap();
+        assert_eq!(conts.lines().count(), 50);
+        assert!(conts.ends_with("line49\n"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn filesplit_period() {
+        use crate::filesplit::{Config, FileSpliter, Period};
+        let dir = std::env::temp_dir().join(format!("ruisutil-period-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pth = dir.join("a.log");
+        let day = Duration::from_secs(24 * 3600);
+        let old = |n: u32| {
+            let tm = std::time::SystemTime::now() - day * n * 10;
+            format!("a.log.{}", crate::strftime(tm, "%Y-%m-%d"))
+        };
+        let olds = [
+            (3, old(3)),
+            (2, format!("{}.gz", old(2))),
+            (1, format!("{}.1", old(1))),
+            (3, "a.log.bak".to_string()),
+        ];
+        for (n, name) in &olds {
+            let fl = std::fs::File::create(dir.join(name)).unwrap();
+            fl.set_modified(std::time::SystemTime::now() - day * *n * 10)
+                .unwrap();
+        }
+        let yday = std::time::SystemTime::now() - day;
+        std::fs::write(&pth, "yesterday\n").unwrap();
+        std::fs::File::options()
+            .append(true)
+            .open(&pth)
+            .unwrap()
+            .set_modified(yday)
+            .unwrap();
+
+        let ctx = Context::background(None);
+        let flspt = FileSpliter::new(
+            &ctx,
+            Config {
+                flpath: pth.to_string_lossy().to_string(),
+                period: Period::Daily,
+                maxage: day * 25,
+                flcount: 3,
+                ..Default::default()
+            },
+        );
+        flspt.start().unwrap();
+        flspt.pushs("today\n");
+        flspt.shutdown();
+        assert_eq!(std::fs::read_to_string(&pth).unwrap(), "today\n");
+        let rotated = dir.join(format!("a.log.{}", crate::strftime(yday, "%Y-%m-%d")));
+        assert_eq!(std::fs::read_to_string(rotated).unwrap(), "yesterday\n");
+        // old3 is past maxage, old2 exceeds the count of 3 files
+        assert!(!dir.join(&olds[0].1).exists());
+        assert!(!dir.join(&olds[1].1).exists());
+        assert!(dir.join(&olds[2].1).exists());
+        // not a name rotate makes
+        assert!(dir.join("a.log.bak").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn filesplit_gzip() {
+        use std::io::Read;
+        let dir = std::env::temp_dir().join(format!("ruisutil-gzip-{}", std::process::id()));
+        let pth = dir.join("a.log");
+        let ctx = Context::background(None);
+        let flspt = crate::filesplit::FileSpliter::new(
+            &ctx,
+            crate::filesplit::Config {
+                flpath: pth.to_string_lossy().to_string(),
+                maxbuf: 100,
+                flsize: 1,
+                flcount: 3,
+                gzip: true,
+                ..Default::default()
+            },
+        );
+        flspt.start().unwrap();
+        let line = format!("{}\n", "x".repeat(600));
+        for _ in 0..8 {
+            flspt.pushs(&line);
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        flspt.shutdown();
+        let gzp = dir.join("a.log.1.gz");
+        let mut conts = String::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(&gzp).unwrap())
+            .read_to_string(&mut conts)
+            .unwrap();
+        assert_eq!(conts, line.repeat(2));
+        assert!(dir.join("a.log.2.gz").exists());
+        assert!(!dir.join("a.log.1").exists());
+        assert!(!dir.join("a.log.3.gz").exists());
+        // nothing left staged for the gzip thread
+        assert!(std::fs::read_dir(&dir)
+            .unwrap()
+            .flatten()
+            .all(|v| !v.file_name().to_string_lossy().contains("rotating")));
+
+        // a crash left a staged file and a half compressed one behind
+        std::fs::write(dir.join("a.log.rotating0"), "staged\n").unwrap();
+        std::fs::write(dir.join("a.log.2.gz.tmp"), "partial").unwrap();
+        let flspt = crate::filesplit::FileSpliter::new(&ctx, (*flspt.config()).clone());
+        flspt.start().unwrap();
+        flspt.shutdown();
+        let mut conts = String::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(&gzp).unwrap())
+            .read_to_string(&mut conts)
+            .unwrap();
+        assert_eq!(conts, "staged\n");
+        assert!(dir.join("a.log.2.gz").exists());
+        assert!(!dir.join("a.log.2.gz.tmp").exists());
+        assert!(!dir.join("a.log.rotating0").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn filesplit_overflow() {
+        use crate::filesplit::{Config, FileSpliter, Overflow};
+        let dir = std::env::temp_dir().join(format!("ruisutil-overflow-{}", std::process::id()));
+        let ctx = Context::background(None);
+        let cfg = Config {
+            flpath: dir.join("a.log").to_string_lossy().to_string(),
+            maxbuf: 3,
+            overflow: Overflow::DropNewest,
+            ..Default::default()
+        };
+        let flspt = FileSpliter::new(&ctx, cfg.clone());
+        for i in 0..5 {
+            flspt.pushs(&format!("drop{}\n", i));
+        }
+        assert_eq!(flspt.dropped(), 2);
+        flspt.shutdown();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("a.log")).unwrap(),
+            "drop0\ndrop1\ndrop2\n"
+        );
+
+        let flspt = FileSpliter::new(
+            &ctx,
+            Config {
+                flpath: dir.join("b.log").to_string_lossy().to_string(),
+                overflow: Overflow::Block,
+                ..cfg.clone()
+            },
+        );
+        flspt.start().unwrap();
+        for i in 0..200 {
+            flspt.pushs(&format!("block{}\n", i));
+        }
+        flspt.shutdown();
+        assert_eq!(flspt.dropped(), 0);
+        let conts = std::fs::read_to_string(dir.join("b.log")).unwrap();
+        assert_eq!(conts.lines().count(), 200);
+
+        // the parent of the file is a file
+        let errs = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let errc = errs.clone();
+        let flspt = FileSpliter::new(
+            &ctx,
+            Config {
+                flpath: dir.join("a.log/c.log").to_string_lossy().to_string(),
+                ..cfg
+            },
+        );
+        let flsptc = flspt.clone();
+        flspt.on_error(move |e| {
+            errc.lock().unwrap().push(e.to_string());
+            // replacing the callback from inside it must not deadlock
+            let errc = errc.clone();
+            flsptc.on_error(move |e| errc.lock().unwrap().push(e.to_string()));
+        });
+        flspt.pushs("err\n");
+        flspt.shutdown();
+        assert_eq!(errs.lock().unwrap().len(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn filesplit_shrink() {
+        use crate::filesplit::{Config, FileSpliter, Overflow};
+        let dir = std::env::temp_dir().join(format!("ruisutil-shrink-{}", std::process::id()));
+        let pth = dir.join("a.log");
+        let ctx = Context::background(None);
+        let cfg = Config {
+            flpath: pth.to_string_lossy().to_string(),
+            maxbuf: 10,
+            overflow: Overflow::DropNewest,
+            ..Default::default()
+        };
+        let flspt = FileSpliter::new(&ctx, cfg.clone());
+        for i in 0..10 {
+            flspt.pushs(&format!("line{}\n", i));
+        }
+        flspt.set_config(Config { maxbuf: 2, ..cfg });
+        // full for the new max, dropped by the policy
+        flspt.pushs("over\n");
+        assert_eq!(flspt.dropped(), 1);
+        flspt.start().unwrap();
+        flspt.flush_and_wait().unwrap();
+        assert_eq!(flspt.dropped(), 1);
+        let conts = std::fs::read_to_string(&pth).unwrap();
+        assert_eq!(conts.lines().count(), 10);
+        flspt.shutdown();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn filesplit_fsync() {
+        use crate::filesplit::{Config, FileSpliter, Fsync};
+        let dir = std::env::temp_dir().join(format!("ruisutil-fsync-{}", std::process::id()));
+        let pth = dir.join("a.log");
+        let ctx = Context::background(None);
+        let flspt = FileSpliter::new(
+            &ctx,
+            Config {
+                flpath: pth.to_string_lossy().to_string(),
+                maxbuf: 1000,
+                fsync: Fsync::Interval(Duration::from_secs(3600)),
+                ..Default::default()
+            },
+        );
+        flspt.flush_and_wait().unwrap();
+        flspt.pushs("early\n");
+        let e = flspt.flush_and_wait().unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::NotConnected);
+        flspt.start().unwrap();
+        for i in 0..100 {
+            flspt.pushs(&format!("line{}\n", i));
+        }
+        flspt.flush_and_wait().unwrap();
+        let conts = std::fs::read_to_string(&pth).unwrap();
+        assert_eq!(conts.lines().count(), 101);
+        flspt.push_sync(b"err\n");
+        flspt.flush_and_wait().unwrap();
+        assert!(std::fs::read_to_string(&pth).unwrap().ends_with("line99\nerr\n"));
+        flspt.shutdown();
+        assert!(flspt.flush_and_wait().is_ok());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn filesplit_async() {
+        let dir = std::env::temp_dir().join(format!("ruisutil-async-{}", std::process::id()));
+        let pth = dir.join("a.log");
+        crate::asyncs::block_on(async move {
+            let ctx = Context::background(None);
+            let flspt = crate::filesplit::FileSpliter::new(
+                &ctx,
+                crate::filesplit::Config {
+                    flpath: pth.to_string_lossy().to_string(),
+                    maxbuf: 1000,
+                    ..Default::default()
+                },
+            );
+            let actx = crate::asyncs::Context::new();
+            // run_async is not Send with async-std, keep it on this task
+            let runs = async {
+                if let Err(e) = flspt.run_async(&actx).await {
+                    println!("run_async err:{}", e);
+                }
+            };
+            let drives = async {
+                for i in 0..100 {
+                    flspt.pushs(&format!("line{}\n", i));
+                }
+                let flsptc = flspt.clone();
+                crate::asyncs::spawn_blocking_io(move || flsptc.flush_and_wait()).await?;
+                let conts = std::fs::read_to_string(&pth)?;
+                assert_eq!(conts.lines().count(), 100);
+                actx.cancel();
+                Ok::<(), std::io::Error>(())
+            };
+            // returns only once run_async ended on the cancel
+            let (_, rt) = futures::join!(runs, drives);
+            rt?;
+            Ok(())
+        })
+        .unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
     #[test]
     fn logs() {
         let cfg = crate::filesplit::Config {
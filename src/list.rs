@@ -15,6 +15,23 @@ impl<T> ListDequeMax<T> {
             max: max,
         }
     }
+    pub fn set_max(&mut self, max: usize) {
+        if max > 0 {
+            self.max = max;
+        }
+        while self.len() > self.max {
+            self.pop();
+        }
+    }
+    /// Like `set_max` but keeps what is queued past the new max, `pop` drains it.
+    pub fn set_max_keep(&mut self, max: usize) {
+        if max > 0 {
+            self.max = max;
+        }
+    }
+    pub fn max(&self) -> usize {
+        self.max
+    }
     pub fn len(&self) -> usize {
         self.ls.len()
     }
@@ -34,4 +51,10 @@ impl<T> ListDequeMax<T> {
     pub fn front(&self) -> Option<&T> {
         self.ls.front()
     }
+    pub fn iter(&self) -> std::collections::linked_list::Iter<'_, T> {
+        self.ls.iter()
+    }
+    pub fn clear(&mut self) {
+        self.ls.clear();
+    }
 }
@@ -0,0 +1,129 @@
+use std::{
+    io,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+/// Level per module path, parsed from `RUST_LOG`-style directives
+/// like `info,mycrate::net=debug,hyper=warn`.
+///
+/// A directive applies to its module and all sub modules, the longest match wins.
+/// A bare level sets the default, a bare module name enables everything for it.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    def: log::LevelFilter,
+    // sorted by name length, longest first
+    dirs: Vec<(String, log::LevelFilter)>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new(log::LevelFilter::Info)
+    }
+}
+
+impl Filter {
+    pub fn new(def: log::LevelFilter) -> Self {
+        Self {
+            def,
+            dirs: Vec::new(),
+        }
+    }
+    pub fn parse(s: &str) -> io::Result<Self> {
+        let mut rt = Self::default();
+        for dir in s.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()) {
+            match dir.find('=') {
+                None => match log::LevelFilter::from_str(dir) {
+                    Ok(lev) => rt.def = lev,
+                    Err(_) => {
+                        rt.directive(dir, log::LevelFilter::Trace);
+                    }
+                },
+                Some(i) => {
+                    let name = dir[..i].trim();
+                    let levs = dir[i + 1..].trim();
+                    let lev = log::LevelFilter::from_str(levs).map_err(|_| {
+                        crate::ioerr(
+                            format!("log filter '{}' level err:{}", dir, levs),
+                            Some(io::ErrorKind::InvalidInput),
+                        )
+                    })?;
+                    if name.is_empty() {
+                        rt.def = lev;
+                    } else {
+                        rt.directive(name, lev);
+                    }
+                }
+            }
+        }
+        Ok(rt)
+    }
+    /// Parses the env var `key`, `defs` is used when it is unset or empty.
+    pub fn from_env(key: &str, defs: &str) -> io::Result<Self> {
+        Self::parse(&crate::envs(key, defs))
+    }
+
+    pub fn default_level(&mut self, lev: log::LevelFilter) -> &mut Self {
+        self.def = lev;
+        self
+    }
+    pub fn directive(&mut self, name: &str, lev: log::LevelFilter) -> &mut Self {
+        match self.dirs.iter_mut().find(|(n, _)| n == name) {
+            Some(v) => v.1 = lev,
+            None => {
+                self.dirs.push((name.to_string(), lev));
+                self.dirs.sort_by_key(|v| std::cmp::Reverse(v.0.len()));
+            }
+        }
+        self
+    }
+
+    /// Level in effect for `target`.
+    pub fn level(&self, target: &str) -> log::LevelFilter {
+        for (name, lev) in &self.dirs {
+            if target.starts_with(name.as_str())
+                && (target.len() == name.len() || target[name.len()..].starts_with("::"))
+            {
+                return *lev;
+            }
+        }
+        self.def
+    }
+    pub fn enabled(&self, target: &str, lev: log::Level) -> bool {
+        lev <= self.level(target)
+    }
+    /// Most verbose level of all directives, for `log::set_max_level`.
+    pub fn max_level(&self) -> log::LevelFilter {
+        self.dirs
+            .iter()
+            .map(|v| v.1)
+            .fold(self.def, |a, b| a.max(b))
+    }
+}
+
+/// Changes the filter of a started `Logger`.
+#[derive(Clone)]
+pub struct FilterHandle {
+    pub(super) inner: Arc<RwLock<Filter>>,
+}
+
+impl FilterHandle {
+    pub fn get(&self) -> Filter {
+        match self.inner.read() {
+            Ok(v) => v.clone(),
+            Err(e) => e.into_inner().clone(),
+        }
+    }
+    pub fn set(&self, f: Filter) {
+        let max = f.max_level();
+        match self.inner.write() {
+            Ok(mut v) => *v = f,
+            Err(e) => *e.into_inner() = f,
+        }
+        log::set_max_level(max);
+    }
+    pub fn set_str(&self, s: &str) -> io::Result<()> {
+        self.set(Filter::parse(s)?);
+        Ok(())
+    }
+}
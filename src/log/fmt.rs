@@ -0,0 +1,202 @@
+use std::{fmt::Write, time::SystemTime};
+
+/// Which parts of a record the formatters put into a line.
+#[derive(Clone, Debug)]
+pub struct Fields {
+    pub time: bool,
+    pub thread: bool,
+    pub file: bool,
+    pub line: bool,
+    pub module: bool,
+    pub target: bool,
+    pub zone: Option<i32>,
+}
+impl Default for Fields {
+    fn default() -> Self {
+        Self {
+            time: true,
+            thread: false,
+            file: false,
+            line: false,
+            module: false,
+            target: false,
+            zone: None,
+        }
+    }
+}
+
+pub trait Formatter: Send + Sync {
+    /// Returns the full line including the trailing `\n`.
+    fn format(&self, flds: &Fields, record: &log::Record) -> String;
+}
+
+/// `LEVEL\t [time] [thread] [file:line] [module] [target]: msg k=v`
+pub struct TextFormatter;
+/// One JSON object per line, key-values of the record go into a `kv` object
+/// so they can not clash with `msg`, `level` and the other built-in keys.
+pub struct JsonFormatter;
+
+fn times(flds: &Fields, fmts: &str) -> String {
+    match flds.zone {
+        Some(v) => crate::strftime_off(SystemTime::now(), fmts, v),
+        None => crate::strftime(SystemTime::now(), fmts),
+    }
+}
+fn thread_name() -> String {
+    let thr = std::thread::current();
+    match thr.name() {
+        Some(v) => v.to_string(),
+        None => format!("{:?}", thr.id()),
+    }
+}
+
+impl Formatter for TextFormatter {
+    fn format(&self, flds: &Fields, record: &log::Record) -> String {
+        let mut msg = format!("{}\t", record.level());
+        if flds.time {
+            let _ = write!(msg, " [{}]", times(flds, "%Y-%m-%d %H:%M:%S"));
+        }
+        if flds.thread {
+            let _ = write!(msg, " [{}]", thread_name());
+        }
+        match (flds.file, flds.line) {
+            (true, true) => {
+                if let Some(flp) = record.file() {
+                    let _ = write!(msg, " [{}:{}]", flp, record.line().unwrap_or(0));
+                }
+            }
+            (true, false) => {
+                if let Some(flp) = record.file() {
+                    let _ = write!(msg, " [{}]", flp);
+                }
+            }
+            (false, true) => {
+                if let Some(v) = record.line() {
+                    let _ = write!(msg, " [{}]", v);
+                }
+            }
+            _ => {}
+        }
+        if flds.module {
+            if let Some(v) = record.module_path() {
+                let _ = write!(msg, " [{}]", v);
+            }
+        }
+        if flds.target {
+            let _ = write!(msg, " [{}]", record.target());
+        }
+        let _ = write!(msg, ": {}", record.args());
+        let _ = record.key_values().visit(&mut TextKvs(&mut msg));
+        msg.push_str(" \n");
+        msg
+    }
+}
+
+impl Formatter for JsonFormatter {
+    fn format(&self, flds: &Fields, record: &log::Record) -> String {
+        let mut msg = String::from("{\"level\":");
+        json_str(&mut msg, record.level().as_str());
+        if flds.time {
+            msg.push_str(",\"time\":");
+            json_str(&mut msg, &times(flds, "%Y-%m-%dT%H:%M:%S%.3f%:z"));
+        }
+        if flds.thread {
+            msg.push_str(",\"thread\":");
+            json_str(&mut msg, &thread_name());
+        }
+        if flds.file {
+            if let Some(v) = record.file() {
+                msg.push_str(",\"file\":");
+                json_str(&mut msg, v);
+            }
+        }
+        if flds.line {
+            if let Some(v) = record.line() {
+                let _ = write!(msg, ",\"line\":{}", v);
+            }
+        }
+        if flds.module {
+            if let Some(v) = record.module_path() {
+                msg.push_str(",\"module\":");
+                json_str(&mut msg, v);
+            }
+        }
+        if flds.target {
+            msg.push_str(",\"target\":");
+            json_str(&mut msg, record.target());
+        }
+        msg.push_str(",\"msg\":");
+        json_str(&mut msg, &record.args().to_string());
+        let kvs = record.key_values();
+        if kvs.count() > 0 {
+            msg.push_str(",\"kv\":{");
+            let _ = kvs.visit(&mut JsonKvs {
+                out: &mut msg,
+                first: true,
+            });
+            msg.push('}');
+        }
+        msg.push_str("}\n");
+        msg
+    }
+}
+
+struct TextKvs<'a>(&'a mut String);
+impl<'kvs> log::kv::VisitSource<'kvs> for TextKvs<'_> {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        let _ = write!(self.0, " {}={}", key, value);
+        Ok(())
+    }
+}
+struct JsonKvs<'a> {
+    out: &'a mut String,
+    first: bool,
+}
+impl<'kvs> log::kv::VisitSource<'kvs> for JsonKvs<'_> {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        if !self.first {
+            self.out.push(',');
+        }
+        self.first = false;
+        json_str(self.out, key.as_str());
+        self.out.push(':');
+        if let Some(v) = value.to_bool() {
+            let _ = write!(self.out, "{}", v);
+        } else if let Some(v) = value.to_i64() {
+            let _ = write!(self.out, "{}", v);
+        } else if let Some(v) = value.to_u64() {
+            let _ = write!(self.out, "{}", v);
+        } else if let Some(v) = value.to_f64().filter(|v| v.is_finite()) {
+            let _ = write!(self.out, "{}", v);
+        } else {
+            json_str(self.out, &value.to_string());
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
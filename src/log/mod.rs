@@ -0,0 +1,506 @@
+use std::{
+    io,
+    sync::{Arc, RwLock},
+};
+
+use crate::filesplit;
+
+pub use filter::{Filter, FilterHandle};
+pub use fmt::{Fields, Formatter, JsonFormatter, TextFormatter};
+pub use sink::{FnSink, MemorySink, Sink, StdioSink};
+
+mod filter;
+mod fmt;
+mod sink;
+
+pub struct Logger {
+    filter: Arc<RwLock<Filter>>,
+    flspt: filesplit::FileSpliter,
+    // the file given to `new` comes first, if it has a path
+    sinks: Vec<sink::SinkEntry>,
+    showstd: bool,
+    errstd: bool,
+    color: Option<bool>,
+    flds: Arc<RwLock<Fields>>,
+    fmt: Box<dyn Formatter>,
+}
+
+impl Logger {
+    /// An empty `cg.flpath` logs to stdio only.
+    pub fn new(ctx: &crate::Context, cg: filesplit::Config) -> Self {
+        let nofl = cg.flpath.is_empty();
+        let flspt = filesplit::FileSpliter::new(ctx, cg);
+        let mut sinks = Vec::new();
+        if !nofl {
+            sinks.push(sink::SinkEntry {
+                lev: log::LevelFilter::Trace,
+                fmt: None,
+                sink: Arc::new(flspt.clone()),
+            });
+        }
+        Self {
+            filter: Arc::new(RwLock::new(Filter::default())),
+            sinks,
+            flspt,
+            showstd: true,
+            errstd: false,
+            color: None,
+            flds: Arc::new(RwLock::new(Fields::default())),
+            fmt: Box::new(TextFormatter),
+        }
+    }
+
+    /// Installs the logger, the returned handle changes it at runtime and shuts it down.
+    pub fn start(mut self) -> io::Result<LogHandle> {
+        let hdl = self.start_sinks()?;
+        let lev = hdl.filter.get().max_level();
+        if let Err(e) = log::set_boxed_logger(Box::new(self)) {
+            hdl.sinks.iter().for_each(|v| v.shutdown());
+            return Err(crate::ioerr(
+                format!("log::set_boxed_logger err:{}", e),
+                None,
+            ));
+        }
+        log::set_max_level(lev);
+        Ok(hdl)
+    }
+    // the global logger can be set only once, so it goes in after every sink is up
+    fn start_sinks(&mut self) -> io::Result<LogHandle> {
+        if self.showstd {
+            let mut std = if self.errstd {
+                StdioSink::split()
+            } else {
+                StdioSink::stdout()
+            };
+            if let Some(v) = self.color {
+                std.set_color(v);
+            }
+            // right after the file
+            let at = if self.flspt.config().flpath.is_empty() { 0 } else { 1 };
+            self.sinks.insert(
+                at,
+                sink::SinkEntry {
+                    lev: log::LevelFilter::Trace,
+                    fmt: None,
+                    sink: Arc::new(std),
+                },
+            );
+        }
+        let hdl = LogHandle {
+            filter: self.filter_handle(),
+            flds: self.flds.clone(),
+            flspt: self.flspt.clone(),
+            sinks: self.sinks.iter().map(|v| v.sink.clone()).collect(),
+        };
+        for (i, v) in hdl.sinks.iter().enumerate() {
+            if let Err(e) = v.start() {
+                hdl.sinks[..i].iter().for_each(|v| v.shutdown());
+                return Err(e);
+            }
+        }
+        Ok(hdl)
+    }
+
+    /// Adds an output next to the file given to `new`.
+    /// `lev` only narrows what passes the logger's filter, the logger's formatter is used.
+    pub fn sink<S: Sink + 'static>(&mut self, lev: log::LevelFilter, s: S) -> &mut Self {
+        self.sinks.push(sink::SinkEntry {
+            lev,
+            fmt: None,
+            sink: Arc::new(s),
+        });
+        self
+    }
+    /// Like `sink` with its own formatter.
+    pub fn sink_fmt<S: Sink + 'static, F: Formatter + 'static>(
+        &mut self,
+        lev: log::LevelFilter,
+        s: S,
+        f: F,
+    ) -> &mut Self {
+        self.sinks.push(sink::SinkEntry {
+            lev,
+            fmt: Some(Box::new(f)),
+            sink: Arc::new(s),
+        });
+        self
+    }
+
+    /// Default level, directives set by `filter` still apply.
+    pub fn level(&mut self, lev: log::Level) -> &mut Self {
+        if let Ok(mut v) = self.filter.write() {
+            v.default_level(lev.to_level_filter());
+        }
+        self
+    }
+    pub fn filter(&mut self, f: Filter) -> &mut Self {
+        if let Ok(mut v) = self.filter.write() {
+            *v = f;
+        }
+        self
+    }
+    /// Keeps working after `start`, changes take effect immediately.
+    pub fn filter_handle(&self) -> FilterHandle {
+        FilterHandle {
+            inner: self.filter.clone(),
+        }
+    }
+    pub fn timezone(&mut self, o: i32) -> &mut Self {
+        if let Ok(mut v) = self.flds.write() {
+            v.zone = Some(o);
+        }
+        self
+    }
+    pub fn hide_stdio(&mut self) -> &mut Self {
+        self.showstd = false;
+        self
+    }
+    /// Sends warn and error to stderr instead of stdout.
+    pub fn stderr_warn(&mut self) -> &mut Self {
+        self.errstd = true;
+        self
+    }
+    /// Colors terminal output by level, detected from the terminal by default.
+    /// Files are never colored.
+    pub fn color(&mut self, on: bool) -> &mut Self {
+        self.color = Some(on);
+        self
+    }
+    pub fn hide_time(&mut self) -> &mut Self {
+        if let Ok(mut v) = self.flds.write() {
+            v.time = false;
+        }
+        self
+    }
+    pub fn show_file_info(&mut self) -> &mut Self {
+        if let Ok(mut v) = self.flds.write() {
+            v.file = true;
+        }
+        self
+    }
+    pub fn show_module(&mut self) -> &mut Self {
+        if let Ok(mut v) = self.flds.write() {
+            v.module = true;
+        }
+        self
+    }
+    pub fn show_line(&mut self) -> &mut Self {
+        if let Ok(mut v) = self.flds.write() {
+            v.line = true;
+        }
+        self
+    }
+    pub fn show_thread(&mut self) -> &mut Self {
+        if let Ok(mut v) = self.flds.write() {
+            v.thread = true;
+        }
+        self
+    }
+    pub fn show_target(&mut self) -> &mut Self {
+        if let Ok(mut v) = self.flds.write() {
+            v.target = true;
+        }
+        self
+    }
+    /// Replaces the default `TextFormatter`, e.g. with `JsonFormatter` for log shipping.
+    pub fn formatter<F: Formatter + 'static>(&mut self, f: F) -> &mut Self {
+        self.fmt = Box::new(f);
+        self
+    }
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        match self.filter.read() {
+            Ok(v) => v.enabled(metadata.target(), metadata.level()),
+            Err(_) => false,
+        }
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let flds = match self.flds.read() {
+            Ok(v) => v,
+            Err(e) => e.into_inner(),
+        };
+        // the default line is shared by all sinks without their own formatter
+        let mut msg = None;
+        for v in &self.sinks {
+            if record.level() > v.lev {
+                continue;
+            }
+            match &v.fmt {
+                Some(f) => v.sink.write(record, &f.format(&flds, record)),
+                None => {
+                    let line = msg.get_or_insert_with(|| self.fmt.format(&flds, record));
+                    v.sink.write(record, line);
+                }
+            }
+        }
+    }
+
+    fn flush(&self) {
+        for v in &self.sinks {
+            v.sink.flush();
+        }
+    }
+}
+
+/// Returned by `Logger::start`.
+#[derive(Clone)]
+pub struct LogHandle {
+    filter: FilterHandle,
+    flds: Arc<RwLock<Fields>>,
+    flspt: filesplit::FileSpliter,
+    sinks: Vec<Arc<dyn Sink>>,
+}
+
+impl LogHandle {
+    pub fn set_level(&self, lev: log::Level) {
+        let mut f = self.filter.get();
+        f.default_level(lev.to_level_filter());
+        self.filter.set(f);
+    }
+    pub fn set_filter(&self, f: Filter) {
+        self.filter.set(f);
+    }
+    pub fn filter(&self) -> &FilterHandle {
+        &self.filter
+    }
+    pub fn fields(&self) -> Fields {
+        match self.flds.read() {
+            Ok(v) => v.clone(),
+            Err(e) => e.into_inner().clone(),
+        }
+    }
+    pub fn set_fields(&self, flds: Fields) {
+        match self.flds.write() {
+            Ok(mut v) => *v = flds,
+            Err(e) => *e.into_inner() = flds,
+        }
+    }
+    pub fn set_timezone(&self, o: Option<i32>) {
+        let mut flds = self.fields();
+        flds.zone = o;
+        self.set_fields(flds);
+    }
+    /// Config of the file given to `Logger::new`, see `FileSpliter::set_config`.
+    /// A logger started without a file doesn't open one here.
+    pub fn set_file_config(&self, cg: filesplit::Config) {
+        self.flspt.set_config(cg);
+    }
+    pub fn flush(&self) {
+        for v in &self.sinks {
+            v.flush();
+        }
+    }
+    /// Turns logging off, writes out what is buffered and joins the file threads.
+    /// The global logger can't be replaced, so this is final.
+    pub fn shutdown(&self) {
+        log::set_max_level(log::LevelFilter::Off);
+        for v in &self.sinks {
+            v.shutdown();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use log::{Level, LevelFilter};
+
+    use super::{
+        Fields, Filter, FnSink, Formatter, JsonFormatter, MemorySink, StdioSink, TextFormatter,
+    };
+
+    fn record_fmt<F: Formatter>(fmt: &F, flds: &Fields) -> String {
+        let kvs: &[(&str, log::kv::Value)] = &[
+            ("user", log::kv::Value::from("ru\"is")),
+            ("n", log::kv::Value::from(3i64)),
+            ("ok", log::kv::Value::from(true)),
+        ];
+        fmt.format(
+            flds,
+            &log::Record::builder()
+                .args(format_args!("hello {}", 1))
+                .level(log::Level::Warn)
+                .target("net")
+                .module_path(Some("ruisutil::net"))
+                .file(Some("src/net.rs"))
+                .line(Some(12))
+                .key_values(&kvs)
+                .build(),
+        )
+    }
+
+    #[test]
+    fn formats() {
+        let mut flds = Fields {
+            time: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            record_fmt(&TextFormatter, &flds),
+            "WARN\t: hello 1 user=ru\"is n=3 ok=true \n"
+        );
+        flds.file = true;
+        flds.line = true;
+        flds.module = true;
+        flds.target = true;
+        assert_eq!(
+            record_fmt(&TextFormatter, &flds),
+            "WARN\t [src/net.rs:12] [ruisutil::net] [net]: hello 1 user=ru\"is n=3 ok=true \n"
+        );
+        assert_eq!(
+            record_fmt(&JsonFormatter, &flds),
+            "{\"level\":\"WARN\",\"file\":\"src/net.rs\",\"line\":12,\"module\":\"ruisutil::net\",\"target\":\"net\",\"msg\":\"hello 1\",\"kv\":{\"user\":\"ru\\\"is\",\"n\":3,\"ok\":true}}\n"
+        );
+        // a kv named like a built-in key does not repeat it
+        let kvs: &[(&str, log::kv::Value)] = &[("msg", log::kv::Value::from("kv"))];
+        assert_eq!(
+            JsonFormatter.format(
+                &Fields {
+                    time: false,
+                    ..Default::default()
+                },
+                &log::Record::builder()
+                    .args(format_args!("hi"))
+                    .level(log::Level::Info)
+                    .key_values(&kvs)
+                    .build(),
+            ),
+            "{\"level\":\"INFO\",\"msg\":\"hi\",\"kv\":{\"msg\":\"kv\"}}\n"
+        );
+        flds.time = true;
+        flds.thread = true;
+        let line = record_fmt(&JsonFormatter, &flds);
+        assert!(line.contains(",\"time\":\""));
+        assert!(line.contains(",\"thread\":\""));
+    }
+
+    #[test]
+    fn filters() {
+        let flt = Filter::parse("warn, ruisutil::net=debug,ruisutil::net::tcp=off,hyper").unwrap();
+        assert_eq!(flt.level("ruisutil"), LevelFilter::Warn);
+        assert_eq!(flt.level("ruisutil::net"), LevelFilter::Debug);
+        assert_eq!(flt.level("ruisutil::net::udp"), LevelFilter::Debug);
+        assert_eq!(flt.level("ruisutil::net::tcp"), LevelFilter::Off);
+        assert_eq!(flt.level("ruisutil::network"), LevelFilter::Warn);
+        assert_eq!(flt.level("hyper::client"), LevelFilter::Trace);
+        assert_eq!(flt.max_level(), LevelFilter::Trace);
+        assert!(flt.enabled("ruisutil::net", Level::Debug));
+        assert!(!flt.enabled("ruisutil::net", Level::Trace));
+        assert!(!flt.enabled("other", Level::Info));
+        assert!(Filter::parse("net=loud").is_err());
+        assert_eq!(Filter::parse("").unwrap().max_level(), LevelFilter::Info);
+    }
+
+    #[test]
+    fn sinks() {
+        let ctx = crate::Context::background(None);
+        let all = MemorySink::new(10);
+        let errs = MemorySink::new(10);
+        let cnt = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let cnts = cnt.clone();
+        let mut lgr = super::Logger::new(&ctx, Default::default());
+        lgr.level(Level::Debug)
+            .hide_time()
+            .sink(LevelFilter::Trace, all.clone())
+            .sink_fmt(LevelFilter::Error, errs.clone(), JsonFormatter)
+            .sink(
+                LevelFilter::Warn,
+                FnSink::new(move |_, _| {
+                    cnts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                }),
+            );
+        for lev in [Level::Trace, Level::Debug, Level::Warn, Level::Error] {
+            log::Log::log(
+                &lgr,
+                &log::Record::builder()
+                    .args(format_args!("{}", lev))
+                    .level(lev)
+                    .build(),
+            );
+        }
+        assert_eq!(
+            all.lines(),
+            vec!["DEBUG\t: DEBUG \n", "WARN\t: WARN \n", "ERROR\t: ERROR \n"]
+        );
+        assert_eq!(
+            errs.lines(),
+            vec!["{\"level\":\"ERROR\",\"msg\":\"ERROR\"}\n"]
+        );
+        assert_eq!(cnt.load(std::sync::atomic::Ordering::SeqCst), 2);
+        all.clear();
+        assert!(all.lines().is_empty());
+
+        let std = StdioSink::split();
+        assert!(std.is_err(Level::Error) && std.is_err(Level::Warn));
+        assert!(!std.is_err(Level::Info));
+        assert!(!StdioSink::stdout().is_err(Level::Error));
+        ctx.stop();
+    }
+
+    struct Probe {
+        up: std::sync::Arc<std::sync::atomic::AtomicIsize>,
+        fail: bool,
+    }
+    impl super::Sink for Probe {
+        fn write(&self, _: &log::Record, _: &str) {}
+        fn start(&self) -> std::io::Result<()> {
+            if self.fail {
+                return Err(crate::ioerr("probe", None));
+            }
+            self.up.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        }
+        fn shutdown(&self) {
+            self.up.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn start_stdio() {
+        let ctx = crate::Context::background(None);
+        // no path, no file sink
+        let mut lgr = super::Logger::new(&ctx, Default::default());
+        let hdl = lgr.start_sinks().unwrap();
+        assert_eq!(hdl.sinks.len(), 1);
+        hdl.sinks.iter().for_each(|v| v.shutdown());
+        ctx.stop();
+    }
+
+    #[test]
+    fn start_fails() {
+        let ctx = crate::Context::background(None);
+        let dir = std::env::temp_dir().join(format!("ruisutil-logstart-{}", std::process::id()));
+        let up = std::sync::Arc::new(std::sync::atomic::AtomicIsize::new(0));
+        let mut lgr = super::Logger::new(
+            &ctx,
+            crate::filesplit::Config {
+                flpath: dir.join("a.log").to_string_lossy().to_string(),
+                ..Default::default()
+            },
+        );
+        lgr.hide_stdio()
+            .sink(
+                LevelFilter::Trace,
+                Probe {
+                    up: up.clone(),
+                    fail: false,
+                },
+            )
+            .sink(
+                LevelFilter::Trace,
+                Probe {
+                    up: up.clone(),
+                    fail: true,
+                },
+            );
+        assert!(lgr.start_sinks().is_err());
+        // the sinks started before the failing one are shut down again
+        assert_eq!(up.load(std::sync::atomic::Ordering::SeqCst), 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
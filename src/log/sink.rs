@@ -0,0 +1,180 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::{Arc, Mutex},
+};
+
+use crate::filesplit;
+
+use super::Formatter;
+
+/// Destination of formatted log lines, added to a `Logger` with `Logger::sink`.
+pub trait Sink: Send + Sync {
+    /// `line` is the output of the sink's formatter, including the trailing `\n`.
+    fn write(&self, record: &log::Record, line: &str);
+    fn flush(&self) {}
+    /// Called once by `Logger::start`, for sinks that need a background worker.
+    fn start(&self) -> io::Result<()> {
+        Ok(())
+    }
+    /// Called by `LogHandle::shutdown`, writes out what is pending and stops the worker.
+    fn shutdown(&self) {
+        self.flush();
+    }
+}
+
+impl Sink for filesplit::FileSpliter {
+    fn write(&self, record: &log::Record, line: &str) {
+        if record.level() == log::Level::Error && self.config().fsync_errors {
+            self.push_sync(line.as_bytes());
+        } else {
+            self.push(line.as_bytes());
+        }
+    }
+    fn flush(&self) {
+        filesplit::FileSpliter::flush(self);
+    }
+    fn start(&self) -> io::Result<()> {
+        filesplit::FileSpliter::start(self)
+    }
+    fn shutdown(&self) {
+        filesplit::FileSpliter::shutdown(self);
+    }
+}
+
+/// Writes to stdout/stderr, colored by level when the stream is a terminal.
+pub struct StdioSink {
+    err: bool,
+    // warn and error go to stderr
+    split: bool,
+    color_out: bool,
+    color_err: bool,
+}
+impl StdioSink {
+    pub fn stdout() -> Self {
+        Self::with(false, false)
+    }
+    pub fn stderr() -> Self {
+        Self::with(true, false)
+    }
+    /// Warn and error to stderr, the rest to stdout.
+    pub fn split() -> Self {
+        Self::with(false, true)
+    }
+    fn with(err: bool, split: bool) -> Self {
+        // https://no-color.org
+        let nocolor = !crate::envs("NO_COLOR", "").is_empty();
+        Self {
+            err,
+            split,
+            color_out: !nocolor && io::stdout().is_terminal(),
+            color_err: !nocolor && io::stderr().is_terminal(),
+        }
+    }
+    /// Overrides the terminal detection.
+    pub fn set_color(&mut self, on: bool) -> &mut Self {
+        self.color_out = on;
+        self.color_err = on;
+        self
+    }
+
+    pub(super) fn is_err(&self, lev: log::Level) -> bool {
+        self.err || (self.split && lev <= log::Level::Warn)
+    }
+}
+impl Sink for StdioSink {
+    fn write(&self, record: &log::Record, line: &str) {
+        let err = self.is_err(record.level());
+        let color = if err { self.color_err } else { self.color_out };
+        let mut buf = String::new();
+        let line = if color {
+            let (cont, end) = match line.strip_suffix('\n') {
+                Some(v) => (v, "\n"),
+                None => (line, ""),
+            };
+            buf.push_str(level_color(record.level()));
+            buf.push_str(cont);
+            buf.push_str("\x1b[0m");
+            buf.push_str(end);
+            buf.as_str()
+        } else {
+            line
+        };
+        if err {
+            let _ = io::stderr().lock().write_all(line.as_bytes());
+        } else {
+            let _ = io::stdout().lock().write_all(line.as_bytes());
+        }
+    }
+    fn flush(&self) {
+        if self.err || self.split {
+            let _ = io::stderr().flush();
+        }
+        if !self.err {
+            let _ = io::stdout().flush();
+        }
+    }
+}
+
+fn level_color(lev: log::Level) -> &'static str {
+    match lev {
+        log::Level::Error => "\x1b[31m",
+        log::Level::Warn => "\x1b[33m",
+        log::Level::Info => "\x1b[32m",
+        log::Level::Debug => "\x1b[34m",
+        log::Level::Trace => "\x1b[90m",
+    }
+}
+
+/// Keeps the last `max` lines in memory, clones share the same ring.
+#[derive(Clone)]
+pub struct MemorySink {
+    buf: Arc<Mutex<crate::ListDequeMax<String>>>,
+}
+impl MemorySink {
+    pub fn new(max: usize) -> Self {
+        Self {
+            buf: Arc::new(Mutex::new(crate::ListDequeMax::new(max))),
+        }
+    }
+    pub fn lines(&self) -> Vec<String> {
+        match self.buf.lock() {
+            Ok(v) => v.iter().cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+    pub fn clear(&self) {
+        if let Ok(mut v) = self.buf.lock() {
+            v.clear();
+        }
+    }
+}
+impl Sink for MemorySink {
+    fn write(&self, _: &log::Record, line: &str) {
+        if let Ok(mut v) = self.buf.lock() {
+            v.push(line.to_string());
+        }
+    }
+}
+
+type SinkFn = dyn Fn(&log::Record, &str) + Send + Sync;
+
+/// Hands every line to a user callback.
+pub struct FnSink {
+    f: Box<SinkFn>,
+}
+impl FnSink {
+    pub fn new<F: Fn(&log::Record, &str) + Send + Sync + 'static>(f: F) -> Self {
+        Self { f: Box::new(f) }
+    }
+}
+impl Sink for FnSink {
+    fn write(&self, record: &log::Record, line: &str) {
+        (self.f)(record, line);
+    }
+}
+
+pub(super) struct SinkEntry {
+    pub lev: log::LevelFilter,
+    pub fmt: Option<Box<dyn Formatter>>,
+    pub sink: Arc<dyn Sink>,
+}
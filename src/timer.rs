@@ -1,5 +1,5 @@
 use std::{
-    sync::atomic::AtomicU64,
+    sync::{atomic::AtomicU64, RwLock},
     time::{Duration, Instant},
 };
 
@@ -11,7 +11,8 @@ pub struct Timer {
 }
 
 struct Inner {
-    start_tm: Instant,
+    // only replaced when the nanos since it near u64::MAX
+    start_tm: RwLock<Instant>,
     dur: AtomicU64,
     tms: AtomicU64,
 }
@@ -19,14 +20,20 @@ impl Timer {
     pub fn new(dur: Duration) -> Self {
         Self {
             inner: ArcMut::new(Inner {
-                start_tm: Instant::now(),
+                start_tm: RwLock::new(Instant::now()),
                 dur: AtomicU64::new(dur.as_nanos() as u64),
                 tms: AtomicU64::new(0),
             }),
         }
     }
+    fn start_tm(&self) -> Instant {
+        match self.inner.start_tm.read() {
+            Ok(v) => *v,
+            Err(e) => *e.into_inner(),
+        }
+    }
     pub fn reset(&self) {
-        let tms = self.inner.start_tm.elapsed();
+        let tms = self.start_tm().elapsed();
         self.inner
             .tms
             .store(tms.as_nanos() as u64, std::sync::atomic::Ordering::Relaxed);
@@ -58,11 +65,12 @@ impl Timer {
             return true;
         }
         let dur = self.inner.dur.load(std::sync::atomic::Ordering::Relaxed);
-        let tmsd = self.inner.start_tm.elapsed().as_nanos() as u64;
+        let tmsd = self.start_tm().elapsed().as_nanos() as u64;
         if tmsd >= tms + dur {
             if tmsd > u64::MAX - 1000000 {
-                unsafe {
-                    self.inner.muts().start_tm = Instant::now();
+                match self.inner.start_tm.write() {
+                    Ok(mut v) => *v = Instant::now(),
+                    Err(e) => *e.into_inner() = Instant::now(),
                 }
                 self.reinit();
             }
@@ -76,7 +84,7 @@ impl Timer {
         if tms <= 0 {
             return Duration::ZERO;
         }
-        let tmsd = self.inner.start_tm + Duration::from_nanos(tms);
+        let tmsd = self.start_tm() + Duration::from_nanos(tms);
         Instant::now().duration_since(tmsd)
     }
 }
@@ -420,6 +420,46 @@ pub fn write_allbuf<T: std::io::Write>(
     Ok(wn)
 }
 
+/// "30s", "5m", "1h30m", "100ms", "1.5d", a bare number is seconds.
+/// Units are ns, us, ms, s, m, h, d.
+pub fn parse_duration(s: &str) -> io::Result<Duration> {
+    let s = s.trim();
+    let err = || {
+        crate::ioerr(
+            format!("duration '{}' err", s),
+            Some(io::ErrorKind::InvalidInput),
+        )
+    };
+    if let Ok(v) = s.parse::<f64>() {
+        return Duration::try_from_secs_f64(v).map_err(|_| err());
+    }
+    let mut rt = Duration::ZERO;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let i = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let num: f64 = rest[..i].parse().map_err(|_| err())?;
+        rest = &rest[i..];
+        let j = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let unit = match rest[..j].trim() {
+            "ns" => 1e-9,
+            "us" | "µs" => 1e-6,
+            "ms" => 1e-3,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            "d" => 86400.0,
+            _ => return Err(err()),
+        };
+        rt += Duration::try_from_secs_f64(num * unit).map_err(|_| err())?;
+        rest = &rest[j..];
+    }
+    Ok(rt)
+}
+
 pub fn env(key: &str) -> Option<String> {
     match std::env::var(key) {
         Err(_) => None,